@@ -3,9 +3,13 @@
 #[macro_use]
 extern crate smallvec;
 extern crate test;
+#[cfg(feature = "serde")]
+extern crate bincode;
 
 use self::test::Bencher;
 use smallvec::{ExtendFromSlice, SmallVec};
+use std::collections::HashMap;
+use std::hash::Hash;
 
 const VEC_SIZE: usize = 16;
 const SPILLED_SIZE: usize = 100;
@@ -97,10 +101,14 @@ make_benches! {
         bench_push_small => gen_push(VEC_SIZE as _),
         bench_insert => gen_insert(SPILLED_SIZE as _),
         bench_insert_small => gen_insert(VEC_SIZE as _),
+        bench_insert_front => gen_insert_front(1000),
+        bench_insert_middle => gen_insert_middle(10_000),
         bench_remove => gen_remove(SPILLED_SIZE as _),
         bench_remove_small => gen_remove(VEC_SIZE as _),
         bench_extend => gen_extend(SPILLED_SIZE as _),
         bench_extend_small => gen_extend(VEC_SIZE as _),
+        bench_extend_filtered => gen_extend_filtered(SPILLED_SIZE as _),
+        bench_extend_filtered_small => gen_extend_filtered(VEC_SIZE as _),
         bench_from_iter => gen_from_iter(SPILLED_SIZE as _),
         bench_from_iter_small => gen_from_iter(VEC_SIZE as _),
         bench_from_slice => gen_from_slice(SPILLED_SIZE as _),
@@ -119,10 +127,14 @@ make_benches! {
         bench_push_vec_small => gen_push(VEC_SIZE as _),
         bench_insert_vec => gen_insert(SPILLED_SIZE as _),
         bench_insert_vec_small => gen_insert(VEC_SIZE as _),
+        bench_insert_front_vec => gen_insert_front(1000),
+        bench_insert_middle_vec => gen_insert_middle(10_000),
         bench_remove_vec => gen_remove(SPILLED_SIZE as _),
         bench_remove_vec_small => gen_remove(VEC_SIZE as _),
         bench_extend_vec => gen_extend(SPILLED_SIZE as _),
         bench_extend_vec_small => gen_extend(VEC_SIZE as _),
+        bench_extend_filtered_vec => gen_extend_filtered(SPILLED_SIZE as _),
+        bench_extend_filtered_vec_small => gen_extend_filtered(VEC_SIZE as _),
         bench_from_iter_vec => gen_from_iter(SPILLED_SIZE as _),
         bench_from_iter_vec_small => gen_from_iter(VEC_SIZE as _),
         bench_from_slice_vec => gen_from_slice(SPILLED_SIZE as _),
@@ -169,6 +181,36 @@ fn gen_insert<V: Vector<u64>>(n: u64, b: &mut Bencher) {
     });
 }
 
+fn gen_insert_front<V: Vector<u64>>(n: u64, b: &mut Bencher) {
+    #[inline(never)]
+    fn insert_noinline<V: Vector<u64>>(vec: &mut V, x: u64) {
+        vec.insert(0, x)
+    }
+
+    b.iter(|| {
+        let mut vec = V::new();
+        for x in 0..n {
+            insert_noinline(&mut vec, x);
+        }
+        vec
+    });
+}
+
+fn gen_insert_middle<V: Vector<u64>>(n: u64, b: &mut Bencher) {
+    #[inline(never)]
+    fn insert_noinline<V: Vector<u64>>(vec: &mut V, p: usize, x: u64) {
+        vec.insert(p, x)
+    }
+
+    b.iter(|| {
+        let mut vec = V::new();
+        for x in 0..n {
+            insert_noinline(&mut vec, x as usize / 2, x);
+        }
+        vec
+    });
+}
+
 fn gen_remove<V: Vector<u64>>(n: usize, b: &mut Bencher) {
     #[inline(never)]
     fn remove_noinline<V: Vector<u64>>(vec: &mut V, p: usize) -> u64 {
@@ -192,6 +234,15 @@ fn gen_extend<V: Vector<u64>>(n: u64, b: &mut Bencher) {
     });
 }
 
+fn gen_extend_filtered<V: Vector<u64>>(n: u64, b: &mut Bencher) {
+    b.iter(|| {
+        let mut vec = V::new();
+        // Keeps about half of `2 * n` elements, with no useful size hint.
+        vec.extend((0..2 * n).filter(|x| x.count_ones() % 2 == 0));
+        vec
+    });
+}
+
 fn gen_from_iter<V: Vector<u64>>(n: u64, b: &mut Bencher) {
     let v: Vec<u64> = (0..n).collect();
     b.iter(|| {
@@ -240,6 +291,17 @@ fn gen_from_elem<V: Vector<u64>>(n: usize, b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn bench_extend_from_drain(b: &mut Bencher) {
+    let v: SmallVec<[u64; VEC_SIZE]> = (0..1000).collect();
+    b.iter(|| {
+        let mut source = v.clone();
+        let mut vec = SmallVec::<[u64; VEC_SIZE]>::new();
+        vec.extend(source.drain(..));
+        vec
+    });
+}
+
 #[bench]
 fn bench_insert_many(b: &mut Bencher) {
     #[inline(never)]
@@ -259,6 +321,17 @@ fn bench_insert_many(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn bench_insert_many_exact_front(b: &mut Bencher) {
+    let base: SmallVec<[u64; VEC_SIZE]> = (0..500).collect();
+    let items: Vec<u64> = (500..1000).collect();
+    b.iter(|| {
+        let mut vec = base.clone();
+        vec.insert_many(0, items.clone().into_iter());
+        vec
+    });
+}
+
 #[bench]
 fn bench_insert_from_slice(b: &mut Bencher) {
     let v: Vec<u64> = (0..SPILLED_SIZE as _).collect();
@@ -293,3 +366,254 @@ fn bench_macro_from_list_vec(b: &mut Bencher) {
         vec
     });
 }
+
+#[bench]
+fn bench_clone_spilled_bytes(b: &mut Bencher) {
+    let v: SmallVec<[u8; 16]> = (0..4096).map(|i| i as u8).collect();
+    b.iter(|| v.clone());
+}
+
+#[bench]
+fn bench_clone_spilled_bytes_vec(b: &mut Bencher) {
+    let v: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+    b.iter(|| v.clone());
+}
+
+#[bench]
+fn bench_into_iter_nth(b: &mut Bencher) {
+    let v: SmallVec<[u64; VEC_SIZE]> = (0..2000).collect();
+    b.iter(|| v.clone().into_iter().nth(1000));
+}
+
+#[bench]
+fn bench_into_iter_nth_vec(b: &mut Bencher) {
+    let v: Vec<u64> = (0..2000).collect();
+    b.iter(|| v.clone().into_iter().nth(1000));
+}
+
+const HASH_KEYS: u64 = 1000;
+const HASH_LOOKUPS: u64 = 1_000_000;
+
+fn gen_hash_map_lookup<K: Clone + Eq + Hash + for<'a> From<&'a [u8]>>(b: &mut Bencher) {
+    let keys: Vec<K> = (0..HASH_KEYS).map(|i| K::from(&i.to_le_bytes()[..])).collect();
+    let map: HashMap<K, u32> = keys.iter().cloned().zip(0..).collect();
+    b.iter(|| {
+        let mut sum = 0u32;
+        for i in 0..HASH_LOOKUPS {
+            sum = sum.wrapping_add(map[&keys[(i % HASH_KEYS) as usize]]);
+        }
+        sum
+    });
+}
+
+#[bench]
+fn bench_hash_map_lookup(b: &mut Bencher) {
+    gen_hash_map_lookup::<SmallVec<[u8; 8]>>(b)
+}
+
+#[bench]
+fn bench_hash_map_lookup_vec(b: &mut Bencher) {
+    gen_hash_map_lookup::<Vec<u8>>(b)
+}
+
+fn gen_extend_unbounded<V: Vector<u64>>(b: &mut Bencher) {
+    b.iter(|| {
+        let mut n = 0;
+        let mut vec = V::new();
+        vec.extend(std::iter::from_fn(|| {
+            n += 1;
+            if n <= 100_000 { Some(n) } else { None }
+        }));
+        vec
+    });
+}
+
+#[bench]
+fn bench_extend_unbounded(b: &mut Bencher) {
+    gen_extend_unbounded::<SmallVec<[u64; VEC_SIZE]>>(b)
+}
+
+#[bench]
+fn bench_extend_unbounded_vec(b: &mut Bencher) {
+    gen_extend_unbounded::<Vec<u64>>(b)
+}
+
+#[bench]
+fn bench_extend_copied_bytes(b: &mut Bencher) {
+    let src: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+    b.iter(|| {
+        let mut vec = SmallVec::<[u8; VEC_SIZE]>::new();
+        vec.extend(src.iter().copied());
+        vec
+    });
+}
+
+#[bench]
+fn bench_extend_copied_bytes_vec(b: &mut Bencher) {
+    let src: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+    b.iter(|| {
+        let mut vec = Vec::new();
+        vec.extend(src.iter().copied());
+        vec
+    });
+}
+
+#[bench]
+fn bench_clear_bytes(b: &mut Bencher) {
+    let mut vec = SmallVec::<[u8; 1024]>::from_elem(0, 1024);
+    b.iter(|| {
+        for _ in 0..1000 {
+            unsafe { vec.set_len(1024) };
+            test::black_box(&mut vec).clear();
+        }
+    });
+}
+
+#[bench]
+fn bench_write_small(b: &mut Bencher) {
+    use std::io::Write;
+
+    b.iter(|| {
+        let mut vec = SmallVec::<[u8; VEC_SIZE]>::new();
+        for i in 0..10_000 {
+            let n = if i % 2 == 0 { 1 } else { 7 };
+            vec.write_all(&[i as u8; 7][..n]).unwrap();
+        }
+        vec
+    });
+}
+
+#[bench]
+fn bench_write_small_vec(b: &mut Bencher) {
+    use std::io::Write;
+
+    b.iter(|| {
+        let mut vec = Vec::new();
+        for i in 0..10_000 {
+            let n = if i % 2 == 0 { 1 } else { 7 };
+            vec.write_all(&[i as u8; 7][..n]).unwrap();
+        }
+        vec
+    });
+}
+
+#[bench]
+fn bench_extend_from_slice_inline_bytes(b: &mut Bencher) {
+    let src = [7u8; 3];
+    b.iter(|| {
+        let mut vec = SmallVec::<[u8; 256]>::new();
+        for _ in 0..1000 {
+            vec.clear();
+            for _ in 0..80 {
+                vec.extend_from_slice(test::black_box(&src[..]));
+            }
+            test::black_box(&mut vec);
+        }
+    });
+}
+
+#[bench]
+fn bench_index_mut_loop(b: &mut Bencher) {
+    let mut vec: SmallVec<[u64; VEC_SIZE]> = (0..SPILLED_SIZE as u64).collect();
+    b.iter(|| {
+        let vec = test::black_box(&mut vec);
+        for i in 0..vec.len() {
+            vec.as_mut_slice()[i] = i as u64;
+        }
+    });
+}
+
+#[bench]
+fn bench_index_mut_loop_hoisted(b: &mut Bencher) {
+    let mut vec: SmallVec<[u64; VEC_SIZE]> = (0..SPILLED_SIZE as u64).collect();
+    b.iter(|| {
+        let slice = test::black_box(&mut vec).as_mut_slice();
+        for i in 0..slice.len() {
+            slice[i] = i as u64;
+        }
+    });
+}
+
+fn sort_keys() -> Vec<[u8; 8]> {
+    let mut x = 0x2545_f491_4f6c_dd1du64;
+    (0..100_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            // Keep a shared prefix so most comparisons look at several bytes.
+            let mut key = x.to_le_bytes();
+            key[0] = 0;
+            key
+        })
+        .collect()
+}
+
+#[bench]
+fn bench_sort_byte_keys(b: &mut Bencher) {
+    let keys: Vec<SmallVec<[u8; 8]>> =
+        sort_keys().iter().map(|k| SmallVec::from_slice(k)).collect();
+    b.iter(|| {
+        let mut keys = keys.clone();
+        keys.sort_unstable();
+        keys
+    });
+}
+
+#[bench]
+fn bench_sort_byte_keys_vec(b: &mut Bencher) {
+    let keys: Vec<Vec<u8>> = sort_keys().iter().map(|k| k.to_vec()).collect();
+    b.iter(|| {
+        let mut keys = keys.clone();
+        keys.sort_unstable();
+        keys
+    });
+}
+
+fn flatten_groups() -> SmallVec<[SmallVec<[u8; 4]>; 8]> {
+    (0..1000u32).map(|i| (0..i % 7).map(|j| j as u8).collect()).collect()
+}
+
+#[bench]
+fn bench_flatten_into(b: &mut Bencher) {
+    let groups = flatten_groups();
+    b.iter(|| {
+        let mut out = SmallVec::<[u8; VEC_SIZE]>::new();
+        groups.clone().flatten_into(&mut out);
+        out
+    });
+}
+
+#[bench]
+fn bench_flatten_extend(b: &mut Bencher) {
+    let groups = flatten_groups();
+    b.iter(|| {
+        let mut out = SmallVec::<[u8; VEC_SIZE]>::new();
+        for inner in groups.clone() {
+            out.extend(inner);
+        }
+        out
+    });
+}
+
+#[cfg(feature = "serde")]
+#[bench]
+fn bench_deserialize_u32s(b: &mut Bencher) {
+    let src: SmallVec<[u32; 64]> = (0..64).collect();
+    let encoded = bincode::serialize(&src).unwrap();
+    b.iter(|| {
+        let decoded: SmallVec<[u32; 64]> = bincode::deserialize(&encoded).unwrap();
+        decoded
+    });
+}
+
+#[cfg(feature = "serde")]
+#[bench]
+fn bench_deserialize_strings(b: &mut Bencher) {
+    let src: SmallVec<[String; 8]> = (0..64).map(|i| i.to_string()).collect();
+    let encoded = bincode::serialize(&src).unwrap();
+    b.iter(|| {
+        let decoded: SmallVec<[String; 8]> = bincode::deserialize(&encoded).unwrap();
+        decoded
+    });
+}
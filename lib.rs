@@ -8,6 +8,23 @@
 //! to the heap for larger allocations.  This can be a useful optimization for improving cache
 //! locality and reducing allocator traffic for workloads that fit within the inline buffer.
 //!
+//! ## Size
+//!
+//! The inline buffer is stored directly in the `SmallVec`, so a `SmallVec<A>` is always at least
+//! as large as `A`: `SmallVec<[u8; 0x10000]>` takes 64 KiB wherever it is stored, whether on the
+//! stack, in another struct, or in every variant of an enum that contains it. On top of the
+//! buffer (or the heap pointer and length, if those are larger) come one word for the
+//! capacity and, without the `union` feature, up to one more word for the enum tag. Choose the
+//! inline size with this in mind, or store the `SmallVec` in a `Box` where its size matters.
+//!
+//! ## Minimum Rust version
+//!
+//! `smallvec` requires Rust 1.61 or later, for `const fn` constructors like
+//! [`SmallVec::from_const_buf`]. Optional dependencies may need a newer compiler: the `rayon`
+//! feature requires whatever version `rayon` itself does.
+//!
+//! [`SmallVec::from_const_buf`]: struct.SmallVec.html#method.from_const_buf
+//!
 //! ## no_std support
 //!
 //! By default, `smallvec` depends on `libstd`. However, it can be configured to use the unstable
@@ -27,11 +44,38 @@
 //!
 //! To use this feature add `features = ["union"]` in the `smallvec` section of Cargo.toml.
 //! Note that this feature requires a nightly compiler (for now).
+//!
+//! ## `capacity_header` feature
+//!
+//! When the `capacity_header` feature is enabled, a spilled `smallvec` stores its capacity at the
+//! start of its heap allocation instead of next to the pointer, and the word that holds the
+//! capacity otherwise always holds the length. The heap variant then needs only the pointer, so
+//! a `smallvec` whose inline buffer is at most one word is one word smaller, which is the same
+//! saving the `union` feature gives for such buffers, on a stable compiler.
+//!
+//! In exchange, reading the capacity of a spilled `smallvec` goes through the pointer, and the
+//! heap buffer is no longer compatible with `Vec`: [`SmallVec::from_vec`] and
+//! [`SmallVec::into_vec`] copy the elements of a spilled vector into a new allocation, and
+//! [`SmallVec::from_raw_parts`] only accepts buffers that come from another `SmallVec`. This
+//! feature has no effect together with the `union` feature, which relies on the capacity to
+//! tell the inline and spilled states apart.
+//!
+//! [`SmallVec::from_vec`]: struct.SmallVec.html#method.from_vec
+//! [`SmallVec::into_vec`]: struct.SmallVec.html#method.into_vec
+//! [`SmallVec::from_raw_parts`]: struct.SmallVec.html#method.from_raw_parts
+//!
+//! ## `spill_hook` feature
+//!
+//! When the `spill_hook` feature is enabled, [`set_spill_hook`] registers a function that is
+//! called every time a `smallvec` moves from its inline buffer to the heap, to collect statistics
+//! for tuning inline sizes. Without the feature, there is no hook and no check for one.
+//!
+//! [`set_spill_hook`]: fn.set_spill_hook.html
 
 #![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(not(feature = "std"), feature(alloc))]
 #![cfg_attr(feature = "union", feature(untagged_unions))]
-#![cfg_attr(feature = "specialization", feature(specialization))]
+#![cfg_attr(feature = "specialization", feature(specialization, trusted_len))]
 #![cfg_attr(feature = "may_dangle", feature(dropck_eyepatch))]
 #![deny(missing_docs)]
 
@@ -42,10 +86,20 @@ extern crate alloc;
 
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use alloc::borrow::Cow;
 
 #[cfg(feature = "serde")]
 extern crate serde;
 
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+#[cfg(feature = "bytemuck")]
+extern crate bytemuck;
+
 extern crate unreachable;
 use unreachable::UncheckedOptionExt;
 
@@ -55,16 +109,33 @@ mod std {
 }
 
 use std::borrow::{Borrow, BorrowMut};
+#[cfg(feature = "std")]
+use std::borrow::Cow;
 use std::cmp;
+use std::convert::TryFrom;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::iter::{IntoIterator, FromIterator, repeat};
+#[cfg(feature = "specialization")]
+use std::iter::TrustedLen;
+#[cfg(feature = "std")]
+use std::alloc::{alloc, handle_alloc_error, Layout};
+#[cfg(not(feature = "std"))]
+use alloc::alloc::{alloc, handle_alloc_error, Layout};
+#[cfg(all(feature = "std", feature = "capacity_header", not(feature = "union")))]
+use std::alloc::dealloc;
+#[cfg(all(not(feature = "std"), feature = "capacity_header", not(feature = "union")))]
+use alloc::alloc::dealloc;
 use std::mem;
-#[cfg(not(feature = "union"))]
+use std::mem::MaybeUninit;
+#[cfg(feature = "union")]
 use std::mem::ManuallyDrop;
 use std::ops;
+use std::ops::{Bound, RangeBounds};
 use std::ptr;
 use std::slice;
+#[cfg(feature = "spill_hook")]
+use std::sync::atomic::{AtomicPtr, Ordering};
 #[cfg(feature = "std")]
 use std::io;
 #[cfg(feature = "serde")]
@@ -73,6 +144,10 @@ use serde::ser::{Serialize, Serializer, SerializeSeq};
 use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
 #[cfg(feature = "serde")]
 use std::marker::PhantomData;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, IntoParallelRefMutIterator};
+#[cfg(feature = "bytemuck")]
+use bytemuck::{Pod, PodCastError};
 
 /// Creates a [`SmallVec`] containing the arguments.
 ///
@@ -223,11 +298,192 @@ impl<T: Clone> ExtendFromSlice<T> for Vec<T> {
     }
 }
 
+/// Convenience trait for constructing a `SmallVec` from a slice, mirroring `[T]::to_vec`
+///
+/// ## Example
+///
+/// ```rust
+/// use smallvec::{SmallVec, ToSmallVec};
+///
+/// let v = b"Test!".to_smallvec::<[u8; 8]>();
+/// assert_eq!(&*v, b"Test!");
+///
+/// let names = ["a".to_owned(), "b".to_owned()];
+/// let v: SmallVec<[String; 2]> = names.to_smallvec();
+/// assert_eq!(&*v, &names);
+/// ```
+pub trait ToSmallVec<T> {
+    /// Clones the slice into a new `SmallVec`
+    fn to_smallvec<A: Array<Item = T>>(&self) -> SmallVec<A>;
+}
+
+impl<T: Clone> ToSmallVec<T> for [T] {
+    /// Goes through `From<&[T]>`, which copies `Copy` items in bulk when the `specialization`
+    /// feature is enabled.
+    #[inline]
+    fn to_smallvec<A: Array<Item = T>>(&self) -> SmallVec<A> {
+        SmallVec::from(self)
+    }
+}
+
+/// Error type for APIs with fallible heap allocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CollectionAllocErr {
+    /// The requested capacity overflows `usize` or exceeds `isize::MAX` bytes
+    CapacityOverflow,
+    /// The allocator returned an error
+    AllocErr {
+        /// The layout that was passed to the allocator
+        layout: Layout,
+    },
+}
+
+impl fmt::Display for CollectionAllocErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CollectionAllocErr::CapacityOverflow => f.write_str("capacity overflow"),
+            CollectionAllocErr::AllocErr { layout } => {
+                write!(f, "memory allocation of {} bytes failed", layout.size())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for CollectionAllocErr {}
+
+fn infallible<T>(result: Result<T, CollectionAllocErr>) -> T {
+    match result {
+        Ok(x) => x,
+        Err(CollectionAllocErr::CapacityOverflow) => panic!("capacity overflow"),
+        Err(CollectionAllocErr::AllocErr { layout }) => handle_alloc_error(layout),
+    }
+}
+
+#[cfg(feature = "spill_hook")]
+static SPILL_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Register a function to be called whenever a `SmallVec` spills: when it moves from its inline
+/// buffer to a heap allocation, including when it is created with a capacity larger than the
+/// inline size. Later reallocations of a spilled vector do not call it.
+///
+/// The hook receives the inline size and the capacity of the new allocation. It is shared by
+/// all threads, replaces any previously registered hook, and is removed by passing `None`.
+///
+/// ```
+/// use smallvec::{set_spill_hook, SmallVec};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static SPILLS: AtomicUsize = AtomicUsize::new(0);
+/// fn count_spill(_inline_size: usize, _new_cap: usize) {
+///     SPILLS.fetch_add(1, Ordering::Relaxed);
+/// }
+///
+/// set_spill_hook(Some(count_spill));
+/// let mut v: SmallVec<[u8; 2]> = SmallVec::new();
+/// v.extend(0..100);
+/// set_spill_hook(None);
+/// assert_eq!(SPILLS.load(Ordering::Relaxed), 1);
+/// ```
+#[cfg(feature = "spill_hook")]
+pub fn set_spill_hook(hook: Option<fn(inline_size: usize, new_cap: usize)>) {
+    let hook = hook.map_or(ptr::null_mut(), |hook| hook as *mut ());
+    SPILL_HOOK.store(hook, Ordering::Release);
+}
+
+#[cfg(feature = "spill_hook")]
+#[inline]
+fn call_spill_hook(inline_size: usize, new_cap: usize) {
+    let hook = SPILL_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // Only `set_spill_hook` stores to `SPILL_HOOK`, always a function pointer of this type.
+        let hook: fn(usize, usize) = unsafe { mem::transmute(hook) };
+        hook(inline_size, new_cap);
+    }
+}
+
+/// Allocate a heap buffer for `capacity` elements, laid out like the buffer of a `Vec`.
+///
+/// Like `Vec`, this rejects buffers of more than `isize::MAX` bytes, which keeps the offsets of
+/// all elements within `isize`.
+#[cfg(any(not(feature = "capacity_header"), feature = "union"))]
+unsafe fn allocate<T>(capacity: usize) -> Result<*mut T, CollectionAllocErr> {
+    let layout = Layout::array::<T>(capacity).map_err(|_| CollectionAllocErr::CapacityOverflow)?;
+    if layout.size() == 0 {
+        // Zero-sized items need no storage, so use a dangling pointer like `Vec`.
+        return Ok(mem::align_of::<T>() as *mut T);
+    }
+    let ptr = alloc(layout) as *mut T;
+    if ptr.is_null() {
+        return Err(CollectionAllocErr::AllocErr { layout });
+    }
+    Ok(ptr)
+}
+
+#[cfg(any(not(feature = "capacity_header"), feature = "union"))]
 unsafe fn deallocate<T>(ptr: *mut T, capacity: usize) {
     let _vec: Vec<T> = Vec::from_raw_parts(ptr, 0, capacity);
     // Let it drop.
 }
 
+/// The layout of a heap buffer for `capacity` elements preceded by the capacity itself, and the
+/// offset of the first element.
+#[cfg(all(feature = "capacity_header", not(feature = "union")))]
+fn header_layout<T>(capacity: usize) -> Result<(Layout, usize), CollectionAllocErr> {
+    Layout::array::<T>(capacity)
+        .and_then(|elements| Layout::new::<usize>().extend(elements))
+        .map_err(|_| CollectionAllocErr::CapacityOverflow)
+}
+
+/// The offset computed by `header_layout`, which does not depend on the capacity: both the size
+/// of the header and the alignment of `T` are powers of two.
+#[cfg(all(feature = "capacity_header", not(feature = "union")))]
+#[inline]
+fn header_offset<T>() -> usize {
+    cmp::max(mem::size_of::<usize>(), mem::align_of::<T>())
+}
+
+/// Allocate a heap buffer for `capacity` elements and record the capacity in front of them. The
+/// returned pointer points to the first element.
+#[cfg(all(feature = "capacity_header", not(feature = "union")))]
+unsafe fn allocate<T>(capacity: usize) -> Result<*mut T, CollectionAllocErr> {
+    let (layout, offset) = header_layout::<T>(capacity)?;
+    debug_assert_eq!(offset, header_offset::<T>());
+    let base = alloc(layout);
+    if base.is_null() {
+        return Err(CollectionAllocErr::AllocErr { layout });
+    }
+    ptr::write(base as *mut usize, capacity);
+    Ok(base.offset(offset as isize) as *mut T)
+}
+
+#[cfg(all(feature = "capacity_header", not(feature = "union")))]
+unsafe fn deallocate<T>(ptr: *mut T, capacity: usize) {
+    // The layout was valid when the buffer was allocated.
+    let (layout, offset) = header_layout::<T>(capacity).unwrap_or_else(|_| debug_unreachable!());
+    dealloc((ptr as *mut u8).offset(-(offset as isize)), layout);
+}
+
+/// Frees a heap buffer returned by `allocate` when dropped, so that it is freed even if dropping
+/// its elements panics.
+struct DeallocOnDrop<T> {
+    ptr: *mut T,
+    capacity: usize,
+}
+
+impl<T> Drop for DeallocOnDrop<T> {
+    fn drop(&mut self) {
+        unsafe { deallocate(self.ptr, self.capacity) }
+    }
+}
+
+/// Read the capacity recorded in front of a buffer returned by `allocate`.
+#[cfg(all(feature = "capacity_header", not(feature = "union")))]
+#[inline]
+unsafe fn heap_capacity<T>(ptr: *const T) -> usize {
+    ptr::read((ptr as *const u8).offset(-(header_offset::<T>() as isize)) as *const usize)
+}
+
 /// An iterator that removes the items from a `SmallVec` and yields them by value.
 ///
 /// Returned from [`SmallVec::drain`][1].
@@ -235,6 +491,15 @@ unsafe fn deallocate<T>(ptr: *mut T, capacity: usize) {
 /// [1]: struct.SmallVec.html#method.drain
 pub struct Drain<'a, T: 'a> {
     iter: slice::IterMut<'a,T>,
+    // Start of the vector's buffer, and its length field. While the drain is alive the length
+    // only covers the elements before the drained range.
+    ptr: *mut T,
+    len_ptr: *mut usize,
+    // The elements after the drained range, which are moved back to close the gap on drop.
+    tail_start: usize,
+    tail_len: usize,
+    // Heap buffer (pointer and capacity) handed over by `drain_and_reset`, freed on drop.
+    heap: Option<(*mut T, usize)>,
 }
 
 impl<'a, T: 'a> Iterator for Drain<'a,T> {
@@ -249,6 +514,18 @@ impl<'a, T: 'a> Iterator for Drain<'a,T> {
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.iter.size_hint()
     }
+
+    fn nth(&mut self, n: usize) -> Option<T> {
+        let skip = cmp::min(n, self.iter.len());
+        let (skipped, rest) = mem::replace(&mut self.iter, [].iter_mut())
+            .into_slice()
+            .split_at_mut(skip);
+        // Remove the skipped elements from the iterator before dropping them in place, so that
+        // a panicking destructor cannot make them be dropped again.
+        self.iter = rest.iter_mut();
+        unsafe { ptr::drop_in_place(skipped) };
+        self.next()
+    }
 }
 
 impl<'a, T: 'a> DoubleEndedIterator for Drain<'a, T> {
@@ -256,40 +533,72 @@ impl<'a, T: 'a> DoubleEndedIterator for Drain<'a, T> {
     fn next_back(&mut self) -> Option<T> {
         self.iter.next_back().map(|reference| unsafe { ptr::read(reference) })
     }
+
+    fn nth_back(&mut self, n: usize) -> Option<T> {
+        let len = self.iter.len();
+        let skip = cmp::min(n, len);
+        let (rest, skipped) = mem::replace(&mut self.iter, [].iter_mut())
+            .into_slice()
+            .split_at_mut(len - skip);
+        // As in `nth`, the skipped elements leave the iterator before being dropped.
+        self.iter = rest.iter_mut();
+        unsafe { ptr::drop_in_place(skipped) };
+        self.next_back()
+    }
 }
 
 impl<'a, T> ExactSizeIterator for Drain<'a, T> { }
 
+unsafe impl<'a, T: Send> Send for Drain<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Drain<'a, T> {}
+
 impl<'a, T: 'a> Drop for Drain<'a,T> {
     fn drop(&mut self) {
         // Destroy the remaining elements.
         for _ in self.by_ref() {}
+
+        unsafe {
+            let start = *self.len_ptr;
+            if self.tail_len > 0 && self.tail_start != start {
+                let src = self.ptr.offset(self.tail_start as isize);
+                ptr::copy(src, self.ptr.offset(start as isize), self.tail_len);
+            }
+            *self.len_ptr = start + self.tail_len;
+        }
+
+        if let Some((ptr, capacity)) = self.heap {
+            unsafe { deallocate(ptr, capacity) }
+        }
     }
 }
 
 #[cfg(feature = "union")]
 #[allow(unions_with_drop_fields)]
 union SmallVecData<A: Array> {
-    inline: A,
+    inline: ManuallyDrop<MaybeUninit<A>>,
     heap: (*mut A::Item, usize),
 }
 
 #[cfg(feature = "union")]
 impl<A: Array> SmallVecData<A> {
     #[inline]
-    unsafe fn inline(&self) -> &A {
-        &self.inline
+    unsafe fn inline(&self) -> *const A::Item {
+        self.inline.as_ptr() as *const A::Item
+    }
+    #[inline]
+    unsafe fn inline_mut(&mut self) -> *mut A::Item {
+        self.inline.as_mut_ptr() as *mut A::Item
     }
     #[inline]
-    unsafe fn inline_mut(&mut self) -> &mut A {
-        &mut self.inline
+    const fn from_inline(inline: A) -> SmallVecData<A> {
+        SmallVecData { inline: ManuallyDrop::new(MaybeUninit::new(inline)) }
     }
     #[inline]
-    fn from_inline(inline: A) -> SmallVecData<A> {
-        SmallVecData { inline }
+    const fn inline_uninit() -> SmallVecData<A> {
+        SmallVecData { inline: ManuallyDrop::new(MaybeUninit::uninit()) }
     }
     #[inline]
-    unsafe fn into_inline(self) -> A { self.inline }
+    unsafe fn into_inline(self) -> A { ManuallyDrop::into_inner(self.inline).assume_init() }
     #[inline]
     unsafe fn heap(&self) -> (*mut A::Item, usize) {
         self.heap
@@ -306,37 +615,47 @@ impl<A: Array> SmallVecData<A> {
 
 #[cfg(not(feature = "union"))]
 enum SmallVecData<A: Array> {
-    Inline(ManuallyDrop<A>),
+    // Only the first `len` items are initialized.
+    Inline(MaybeUninit<A>),
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
     Heap((*mut A::Item, usize)),
+    // The length is in `SmallVec::capacity` and the capacity in front of the buffer.
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    Heap(*mut A::Item),
 }
 
 #[cfg(not(feature = "union"))]
 impl<A: Array> SmallVecData<A> {
     #[inline]
-    unsafe fn inline(&self) -> &A {
+    unsafe fn inline(&self) -> *const A::Item {
         match *self {
-            SmallVecData::Inline(ref a) => a,
+            SmallVecData::Inline(ref a) => a.as_ptr() as *const A::Item,
             _ => debug_unreachable!(),
         }
     }
     #[inline]
-    unsafe fn inline_mut(&mut self) -> &mut A {
+    unsafe fn inline_mut(&mut self) -> *mut A::Item {
         match *self {
-            SmallVecData::Inline(ref mut a) => a,
+            SmallVecData::Inline(ref mut a) => a.as_mut_ptr() as *mut A::Item,
             _ => debug_unreachable!(),
         }
     }
     #[inline]
-    fn from_inline(inline: A) -> SmallVecData<A> {
-        SmallVecData::Inline(ManuallyDrop::new(inline))
+    const fn from_inline(inline: A) -> SmallVecData<A> {
+        SmallVecData::Inline(MaybeUninit::new(inline))
+    }
+    #[inline]
+    const fn inline_uninit() -> SmallVecData<A> {
+        SmallVecData::Inline(MaybeUninit::uninit())
     }
     #[inline]
     unsafe fn into_inline(self) -> A {
         match self {
-            SmallVecData::Inline(a) => ManuallyDrop::into_inner(a),
+            SmallVecData::Inline(a) => a.assume_init(),
             _ => debug_unreachable!(),
         }
     }
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
     #[inline]
     unsafe fn heap(&self) -> (*mut A::Item, usize) {
         match *self {
@@ -344,6 +663,7 @@ impl<A: Array> SmallVecData<A> {
             _ => debug_unreachable!(),
         }
     }
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
     #[inline]
     unsafe fn heap_mut(&mut self) -> &mut (*mut A::Item, usize) {
         match *self {
@@ -351,10 +671,29 @@ impl<A: Array> SmallVecData<A> {
             _ => debug_unreachable!(),
         }
     }
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
     #[inline]
     fn from_heap(ptr: *mut A::Item, len: usize) -> SmallVecData<A> {
         SmallVecData::Heap((ptr, len))
     }
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    #[inline]
+    unsafe fn heap(&self) -> *mut A::Item {
+        match *self {
+            SmallVecData::Heap(ptr) => ptr,
+            _ => debug_unreachable!(),
+        }
+    }
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    #[inline]
+    fn from_heap(ptr: *mut A::Item) -> SmallVecData<A> {
+        SmallVecData::Heap(ptr)
+    }
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    #[inline]
+    fn is_heap(&self) -> bool {
+        matches!(*self, SmallVecData::Heap(_))
+    }
 }
 
 unsafe impl<A: Array + Send> Send for SmallVecData<A> {}
@@ -390,19 +729,21 @@ pub struct SmallVec<A: Array> {
     // The capacity field is used to determine which of the storage variants is active:
     // If capacity <= A::size() then the inline variant is used and capacity holds the current length of the vector (number of elements actually in use).
     // If capacity > A::size() then the heap variant is used and capacity holds the size of the memory allocation.
+    // With the `capacity_header` feature, the variant of `data` tells them apart instead, and
+    // capacity always holds the length; the size of the allocation is stored at its start.
     capacity: usize,
     data: SmallVecData<A>,
 }
 
 impl<A: Array> SmallVec<A> {
     /// Construct an empty vector
+    ///
+    /// The new vector does not allocate, and its `capacity()` is `inline_size()`.
     #[inline]
     pub fn new() -> SmallVec<A> {
-        unsafe {
-            SmallVec {
-                capacity: 0,
-                data: SmallVecData::from_inline(mem::uninitialized()),
-            }
+        SmallVec {
+            capacity: 0,
+            data: SmallVecData::inline_uninit(),
         }
     }
 
@@ -426,9 +767,43 @@ impl<A: Array> SmallVec<A> {
         v
     }
 
+    /// Construct a vector of `n` elements, each produced by a call to `f`.
+    ///
+    /// If `f` panics, the elements produced so far are dropped.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut next = 1;
+    /// let v: SmallVec<[u32; 4]> = SmallVec::from_repeat_with(5, || { next *= 2; next });
+    /// assert_eq!(&*v, &[2, 4, 8, 16, 32]);
+    /// ```
+    pub fn from_repeat_with<F: FnMut() -> A::Item>(n: usize, mut f: F) -> Self {
+        let mut v = SmallVec::<A>::with_capacity(n);
+        unsafe {
+            let (ptr, len_ptr, _) = v.triple_mut();
+            let mut local_len = SetLenOnDrop::new(len_ptr);
+            for _ in 0..n {
+                ptr::write(ptr.offset(local_len.local_len as isize), f());
+                local_len.increment_len(1);
+            }
+        }
+        v
+    }
+
     /// Construct a new `SmallVec` from a `Vec<A::Item>`.
     ///
-    /// Elements will be copied to the inline buffer if vec.capacity() <= A::size().
+    /// Elements will be copied to the inline buffer if vec.capacity() <= A::size(). Otherwise the
+    /// `Vec`'s allocation is reused as is, so the vector starts out spilled with the same
+    /// capacity.
+    ///
+    /// A spilled `SmallVec` always has a capacity greater than `A::size()`, so a round trip
+    /// through [`into_vec`](#method.into_vec) and `from_vec` never copies the elements of a
+    /// spilled vector, unless the `Vec` was shrunk to `A::size()` or less in between.
+    ///
+    /// With the `capacity_header` feature, the heap buffer of a `Vec` cannot be reused: the
+    /// elements are moved inline if they fit, and into a new allocation of exactly the right
+    /// size otherwise.
     ///
     /// ```rust
     /// use smallvec::SmallVec;
@@ -440,12 +815,15 @@ impl<A: Array> SmallVec<A> {
     /// ```
     #[inline]
     pub fn from_vec(mut vec: Vec<A::Item>) -> SmallVec<A> {
+        if cfg!(all(feature = "capacity_header", not(feature = "union"))) {
+            return vec.into_iter().collect();
+        }
         if vec.capacity() <= A::size() {
             unsafe {
-                let mut data = SmallVecData::<A>::from_inline(mem::uninitialized());
+                let mut data = SmallVecData::<A>::inline_uninit();
                 let len = vec.len();
                 vec.set_len(0);
-                ptr::copy_nonoverlapping(vec.as_ptr(), data.inline_mut().ptr_mut(), len);
+                ptr::copy_nonoverlapping(vec.as_ptr(), data.inline_mut(), len);
 
                 SmallVec {
                     capacity: len,
@@ -456,10 +834,41 @@ impl<A: Array> SmallVec<A> {
             let (ptr, cap, len) = (vec.as_mut_ptr(), vec.capacity(), vec.len());
             mem::forget(vec);
 
-            SmallVec {
-                capacity: cap,
-                data: SmallVecData::from_heap(ptr, len),
-            }
+            let mut v = SmallVec::new();
+            unsafe { v.set_heap(ptr, len, cap) };
+            v
+        }
+    }
+
+    /// Construct a new `SmallVec` from a `Vec<A::Item>`, keeping its capacity.
+    ///
+    /// The elements are moved inline only if `vec.capacity() <= A::size()`. Otherwise the new
+    /// vector is spilled with the same capacity as `vec`, however few elements it holds, so it
+    /// can grow that far without reallocating.
+    ///
+    /// By default, [`from_vec`](#method.from_vec) makes the same decision and this simply
+    /// reuses the `Vec`'s allocation; use it to make the intent explicit. With the
+    /// `capacity_header` feature, where `from_vec` moves the elements into an allocation of
+    /// exactly their length, this moves them into a new allocation of the same capacity instead.
+    ///
+    /// ```rust
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut vec = Vec::with_capacity(100);
+    /// vec.extend_from_slice(&[1, 2]);
+    /// let small_vec: SmallVec<[u8; 4]> = SmallVec::from_vec_keep_capacity(vec);
+    ///
+    /// assert!(small_vec.spilled());
+    /// assert_eq!(small_vec.capacity(), 100);
+    /// assert_eq!(&*small_vec, &[1, 2]);
+    /// ```
+    pub fn from_vec_keep_capacity(vec: Vec<A::Item>) -> SmallVec<A> {
+        if cfg!(all(feature = "capacity_header", not(feature = "union"))) && vec.capacity() > A::size() {
+            let mut v = SmallVec::with_capacity(vec.capacity());
+            v.extend(vec);
+            v
+        } else {
+            SmallVec::from_vec(vec)
         }
     }
 
@@ -486,6 +895,10 @@ impl<A: Array> SmallVec<A> {
     /// copying elements. Also sets the length, which must be less or
     /// equal to the size of `buf`.
     ///
+    /// The elements of `buf` past `len` are forgotten, not dropped, so for element types with
+    /// a destructor they are leaked. Use
+    /// [`from_buf_and_len_dropping`](#method.from_buf_and_len_dropping) to drop them instead.
+    ///
     /// ```rust
     /// use smallvec::SmallVec;
     ///
@@ -500,6 +913,25 @@ impl<A: Array> SmallVec<A> {
         unsafe { SmallVec::from_buf_and_len_unchecked(buf, len) }
     }
 
+    /// Like [`from_buf_and_len`](#method.from_buf_and_len), but drops the elements of `buf`
+    /// past `len` instead of leaking them.
+    ///
+    /// ```rust
+    /// use smallvec::SmallVec;
+    ///
+    /// let buf = [String::from("a"), String::from("b"), String::from("unused")];
+    /// let small_vec: SmallVec<_> = SmallVec::from_buf_and_len_dropping(buf, 2);
+    ///
+    /// assert_eq!(&*small_vec, &["a", "b"]);
+    /// ```
+    #[inline]
+    pub fn from_buf_and_len_dropping(buf: A, len: usize) -> SmallVec<A> {
+        assert!(len <= A::size());
+        let mut v = SmallVec::from_buf(buf);
+        v.truncate(len);
+        v
+    }
+
     /// Constructs a new `SmallVec` on the stack from an `A` without
     /// copying elements. Also sets the length. The user is responsible
     /// for ensuring that `len <= A::size()`.
@@ -515,7 +947,7 @@ impl<A: Array> SmallVec<A> {
     /// assert_eq!(&*small_vec, &[1, 2, 3, 4, 5]);
     /// ```
     #[inline]
-    pub unsafe fn from_buf_and_len_unchecked(buf: A, len: usize) -> SmallVec<A> {
+    pub const unsafe fn from_buf_and_len_unchecked(buf: A, len: usize) -> SmallVec<A> {
         SmallVec {
             capacity: len,
             data: SmallVecData::from_inline(buf),
@@ -533,6 +965,17 @@ impl<A: Array> SmallVec<A> {
         *len_ptr = new_len;
     }
 
+    /// The maximum number of elements this vector can hold inline, as a constant.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// const _: () = assert!(SmallVec::<[u8; 8]>::INLINE_CAPACITY == 8);
+    /// let companion = [0u16; SmallVec::<[u8; 8]>::INLINE_CAPACITY];
+    /// assert_eq!(companion.len(), 8);
+    /// ```
+    pub const INLINE_CAPACITY: usize = A::SIZE;
+
     /// The maximum number of elements this vector can hold inline
     #[inline]
     pub fn inline_size(&self) -> usize {
@@ -552,6 +995,9 @@ impl<A: Array> SmallVec<A> {
     }
 
     /// The number of items the vector can hold without reallocating
+    ///
+    /// This is never less than `inline_size()`. In particular, a vector created with `new()`,
+    /// `default()` or `with_capacity(0)` reports exactly `inline_size()`, not 0.
     #[inline]
     pub fn capacity(&self) -> usize {
         self.triple().2
@@ -559,6 +1005,7 @@ impl<A: Array> SmallVec<A> {
 
     /// Returns a tuple with (data ptr, len, capacity)
     /// Useful to get all SmallVec properties with a single check of the current storage variant.
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
     #[inline]
     fn triple(&self) -> (*const A::Item, usize, usize) {
         unsafe {
@@ -566,12 +1013,13 @@ impl<A: Array> SmallVec<A> {
                 let (ptr, len) = self.data.heap();
                 (ptr, len, self.capacity)
             } else {
-                (self.data.inline().ptr(), self.capacity, A::size())
+                (self.data.inline(), self.capacity, A::size())
             }
         }
     }
 
     /// Returns a tuple with (data ptr, len ptr, capacity)
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
     #[inline]
     fn triple_mut(&mut self) -> (*mut A::Item, &mut usize, usize) {
         unsafe {
@@ -579,29 +1027,169 @@ impl<A: Array> SmallVec<A> {
                 let &mut (ptr, ref mut len_ptr) = self.data.heap_mut();
                 (ptr, len_ptr, self.capacity)
             } else {
-                (self.data.inline_mut().ptr_mut(), &mut self.capacity, A::size())
+                (self.data.inline_mut(), &mut self.capacity, A::size())
+            }
+        }
+    }
+
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    #[inline]
+    fn triple(&self) -> (*const A::Item, usize, usize) {
+        unsafe {
+            if self.spilled() {
+                let ptr = self.data.heap();
+                (ptr, self.capacity, heap_capacity(ptr))
+            } else {
+                (self.data.inline(), self.capacity, A::size())
+            }
+        }
+    }
+
+    /// Returns a tuple with (data ptr, len ptr, capacity)
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    #[inline]
+    fn triple_mut(&mut self) -> (*mut A::Item, &mut usize, usize) {
+        unsafe {
+            if self.spilled() {
+                let ptr = self.data.heap();
+                (ptr, &mut self.capacity, heap_capacity(ptr))
+            } else {
+                (self.data.inline_mut(), &mut self.capacity, A::size())
             }
         }
     }
 
+    /// Switch to the heap buffer `ptr`, which holds `len` elements and has room for `capacity`.
+    /// The previous storage is neither dropped nor freed.
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
+    #[inline]
+    unsafe fn set_heap(&mut self, ptr: *mut A::Item, len: usize, capacity: usize) {
+        self.data = SmallVecData::from_heap(ptr, len);
+        self.capacity = capacity;
+    }
+
+    /// Switch to the heap buffer `ptr`, which holds `len` elements and has room for `capacity`.
+    /// The previous storage is neither dropped nor freed.
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    #[inline]
+    unsafe fn set_heap(&mut self, ptr: *mut A::Item, len: usize, capacity: usize) {
+        debug_assert_eq!(heap_capacity(ptr), capacity);
+        self.data = SmallVecData::from_heap(ptr);
+        self.capacity = len;
+    }
+
     /// Returns `true` if the data has spilled into a separate heap-allocated buffer.
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
     #[inline]
     pub fn spilled(&self) -> bool {
         self.capacity > A::size()
     }
 
-    /// Empty the vector and return an iterator over its former contents.
-    pub fn drain(&mut self) -> Drain<A::Item> {
+    /// Returns `true` if the data has spilled into a separate heap-allocated buffer.
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    #[inline]
+    pub fn spilled(&self) -> bool {
+        self.data.is_heap()
+    }
+
+    /// Returns a pointer to the heap buffer if the data has spilled, or `None` if the elements
+    /// are stored inline.
+    ///
+    /// The pointer stays valid until the vector is reallocated, shrunk back inline or dropped.
+    #[inline]
+    pub fn heap_ptr(&self) -> Option<*const A::Item> {
+        if self.spilled() {
+            Some(self.triple().0)
+        } else {
+            None
+        }
+    }
+
+    /// Remove the elements in `range` from the vector and return an iterator over them.
+    ///
+    /// The elements after the range are moved to close the gap when the iterator is dropped;
+    /// elements of the range that were not consumed are dropped along with it.
+    ///
+    /// Panics if the start of the range is greater than its end, or if its end is greater than
+    /// the length of the vector.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3, 4, 5]);
+    /// assert_eq!(v.drain(1..3).collect::<Vec<_>>(), &[2, 3]);
+    /// assert_eq!(&*v, &[1, 4, 5]);
+    /// assert_eq!(v.drain(..).len(), 3);
+    /// assert!(v.is_empty());
+    /// ```
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> Drain<'_, A::Item> {
         unsafe {
-            let ptr = self.as_mut_ptr();
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let len = *len_ptr;
+            let start = match range.start_bound() {
+                Bound::Included(&n) => n,
+                Bound::Excluded(&n) => n.checked_add(1).expect("range start overflows usize"),
+                Bound::Unbounded => 0,
+            };
+            let end = match range.end_bound() {
+                Bound::Included(&n) => n.checked_add(1).expect("range end overflows usize"),
+                Bound::Excluded(&n) => n,
+                Bound::Unbounded => len,
+            };
+            assert!(start <= end, "drain range starts at {} but ends at {}", start, end);
+            assert!(end <= len, "drain range end {} is out of bounds for length {}", end, len);
+
+            // Until the drain is dropped, only the elements before the range are in the
+            // vector, so a leaked drain leaves it valid.
+            *len_ptr = start;
+
+            let slice = slice::from_raw_parts_mut(ptr.offset(start as isize), end - start);
 
-            let current_len = self.len();
-            self.set_len(0);
+            Drain {
+                iter: slice.iter_mut(),
+                ptr,
+                len_ptr,
+                tail_start: end,
+                tail_len: len - end,
+                heap: None,
+            }
+        }
+    }
+
+    /// Empty the vector and return an iterator over its former contents, like
+    /// [`drain`](#method.drain), but also give up the heap allocation.
+    ///
+    /// The vector is returned to its inline state right away; if it had spilled, the heap
+    /// buffer is freed once the returned `Drain` is dropped. This holds however the `Drain` is
+    /// consumed, including from the back with `rev()`. A plain `drain(..)` keeps the buffer.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 2]> = SmallVec::from_slice(&[1, 2, 3]);
+    /// assert!(v.spilled());
+    /// assert_eq!(v.drain_and_reset().count(), 3);
+    /// assert!(!v.spilled());
+    /// assert_eq!(v.capacity(), 2);
+    /// ```
+    pub fn drain_and_reset(&mut self) -> Drain<'_, A::Item> {
+        if !self.spilled() {
+            return self.drain(..);
+        }
+        unsafe {
+            let (ptr, &mut len, capacity) = self.triple_mut();
+            self.data = SmallVecData::inline_uninit();
+            self.capacity = 0;
 
-            let slice = slice::from_raw_parts_mut(ptr, current_len);
+            let slice = slice::from_raw_parts_mut(ptr, len);
 
             Drain {
                 iter: slice.iter_mut(),
+                ptr,
+                len_ptr: &mut self.capacity,
+                tail_start: len,
+                tail_len: 0,
+                heap: Some((ptr, capacity)),
             }
         }
     }
@@ -615,6 +1203,9 @@ impl<A: Array> SmallVec<A> {
                 self.reserve(1);
             }
             let (ptr, len_ptr, _) = self.triple_mut();
+            // `reserve` keeps the buffer within `isize::MAX` bytes, so the offset cannot wrap.
+            // Only zero-sized items, whose offsets are all 0, can have longer lengths.
+            debug_assert!(mem::size_of::<A::Item>() == 0 || len < isize::MAX as usize);
             *len_ptr = len + 1;
             ptr::write(ptr.offset(len as isize), value);
         }
@@ -634,42 +1225,91 @@ impl<A: Array> SmallVec<A> {
         }
     }
 
+    /// Remove the last `k` elements (or all of them, if there are fewer) and return them in a
+    /// new vector, in their original order.
+    ///
+    /// The elements are moved, not cloned; the returned vector stores them inline if they fit.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3, 4, 5]);
+    /// assert_eq!(&*v.pop_n(2), &[4, 5]);
+    /// assert_eq!(&*v, &[1, 2, 3]);
+    /// ```
+    pub fn pop_n(&mut self, k: usize) -> SmallVec<A> {
+        let len = self.len();
+        let k = cmp::min(k, len);
+        let mut popped = SmallVec::with_capacity(k);
+        unsafe {
+            let (ptr, len_ptr, _) = self.triple_mut();
+            *len_ptr = len - k;
+            ptr::copy_nonoverlapping(ptr.offset((len - k) as isize), popped.triple_mut().0, k);
+            popped.set_len(k);
+        }
+        popped
+    }
+
     /// Re-allocate to set the capacity to `max(new_cap, inline_size())`.
     ///
     /// Panics if `new_cap` is less than the vector's length.
+    ///
+    /// To grow, use [`reserve_exact(new_cap - len())`](#method.reserve_exact); to shrink, use
+    /// [`shrink_to(new_cap)`](#method.shrink_to).
+    #[deprecated(note = "Use `reserve_exact` or `shrink_to` instead")]
     pub fn grow(&mut self, new_cap: usize) {
+        self.set_capacity(new_cap)
+    }
+
+    fn set_capacity(&mut self, new_cap: usize) {
+        infallible(self.try_set_capacity(new_cap))
+    }
+
+    /// Move the elements to a buffer of `new_cap` elements, or to inline storage if they fit
+    /// there. Does nothing if the vector already has that capacity or is inline and stays so.
+    fn try_set_capacity(&mut self, new_cap: usize) -> Result<(), CollectionAllocErr> {
         unsafe {
             let (ptr, &mut len, cap) = self.triple_mut();
             let unspilled = !self.spilled();
             assert!(new_cap >= len);
             if new_cap <= self.inline_size() {
                 if unspilled {
-                    return;
+                    return Ok(());
                 }
-                self.data = SmallVecData::from_inline(mem::uninitialized());
-                ptr::copy_nonoverlapping(ptr, self.data.inline_mut().ptr_mut(), len);
+                self.data = SmallVecData::inline_uninit();
+                ptr::copy_nonoverlapping(ptr, self.data.inline_mut(), len);
+                self.capacity = len;
             } else if new_cap != cap {
-                let mut vec = Vec::with_capacity(new_cap);
-                let new_alloc = vec.as_mut_ptr();
-                mem::forget(vec);
+                let new_alloc = allocate(new_cap)?;
                 ptr::copy_nonoverlapping(ptr, new_alloc, len);
-                self.data = SmallVecData::from_heap(new_alloc, len);
-                self.capacity = new_cap;
+                self.set_heap(new_alloc, len, new_cap);
                 if unspilled {
-                    return;
+                    #[cfg(feature = "spill_hook")]
+                    call_spill_hook(self.inline_size(), new_cap);
+                    return Ok(());
                 }
+            } else {
+                // Already spilled with exactly this capacity; keep the current buffer.
+                return Ok(());
             }
             deallocate(ptr, cap);
         }
+        Ok(())
     }
 
     /// Reserve capacity for `additional` more elements to be inserted.
     ///
-    /// May reserve more space to avoid frequent reallocations.
+    /// May reserve more space to avoid frequent reallocations: when the vector needs to grow,
+    /// the new capacity is the next power of two of `len() + additional`, regardless of how the
+    /// current capacity was reached. A capacity set by `reserve_exact` therefore snaps back to a
+    /// power of two on the next growth.
     ///
     /// If the new capacity would overflow `usize` then it will be set to `usize::max_value()`
     /// instead. (This means that inserting `additional` new elements is not guaranteed to be
     /// possible after calling this function.)
+    ///
+    /// Panics with "capacity overflow" if the buffer would take more than `isize::MAX` bytes, so
+    /// the length of a vector of non-zero-sized items always fits in an `isize`.
     #[inline]
     pub fn reserve(&mut self, additional: usize) {
         // prefer triple_mut() even if triple() would work
@@ -680,42 +1320,254 @@ impl<A: Array> SmallVec<A> {
             let new_cap = len.checked_add(additional).
                 and_then(usize::checked_next_power_of_two).
                 unwrap_or(usize::max_value());
-            self.grow(new_cap);
+            self.set_capacity(new_cap);
         }
     }
 
     /// Reserve the minimum capacity for `additional` more elements to be inserted.
     ///
-    /// Panics if the new capacity overflows `usize`.
+    /// To build a vector of known final size without power-of-two waste, call this once with
+    /// the full size before pushing: pushes that stay within the reserved capacity never
+    /// reallocate. Growth beyond it is amortized as described for [`reserve`](#method.reserve);
+    /// the vector does not remember that it was sized exactly, since storing a growth policy
+    /// would make every `SmallVec` larger.
+    ///
+    /// Panics if the new capacity overflows `usize`, or if the buffer would take more than
+    /// `isize::MAX` bytes.
     pub fn reserve_exact(&mut self, additional: usize) {
         let (_, &mut len, cap) = self.triple_mut();
         if cap - len < additional {
             match len.checked_add(additional) {
-                Some(cap) => self.grow(cap),
+                Some(cap) => self.set_capacity(cap),
                 None => panic!("reserve_exact overflow"),
             }
         }
     }
 
-    /// Shrink the capacity of the vector as much as possible.
-    ///
-    /// When possible, this will move data from an external heap buffer to the vector's inline
-    /// storage.
-    pub fn shrink_to_fit(&mut self) {
-        if !self.spilled() {
-            return;
-        }
+    /// Reserve capacity for `additional` more elements to be inserted, like
+    /// [`reserve`](#method.reserve), but return an error instead of panicking or aborting if
+    /// the capacity overflows or the allocation fails.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), CollectionAllocErr> {
+        let (_, &mut len, cap) = self.triple_mut();
+        if cap - len < additional {
+            let new_cap = len.checked_add(additional)
+                .and_then(usize::checked_next_power_of_two)
+                .ok_or(CollectionAllocErr::CapacityOverflow)?;
+            self.try_set_capacity(new_cap)?;
+        }
+        Ok(())
+    }
+
+    /// Reserve the minimum capacity for `additional` more elements to be inserted, like
+    /// [`reserve_exact`](#method.reserve_exact), but return an error instead of panicking or
+    /// aborting if the capacity overflows or the allocation fails.
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), CollectionAllocErr> {
+        let (_, &mut len, cap) = self.triple_mut();
+        if cap - len < additional {
+            let new_cap = len.checked_add(additional)
+                .ok_or(CollectionAllocErr::CapacityOverflow)?;
+            self.try_set_capacity(new_cap)?;
+        }
+        Ok(())
+    }
+
+    /// Collect an iterator into a new `SmallVec`, returning an error instead of aborting if an
+    /// allocation fails.
+    ///
+    /// On error, the elements collected so far are dropped.
+    ///
+    /// ```
+    /// use smallvec::{CollectionAllocErr, SmallVec};
+    ///
+    /// let v = SmallVec::<[u8; 4]>::try_collect(0..10).unwrap();
+    /// assert_eq!(v.len(), 10);
+    ///
+    /// let err = SmallVec::<[u64; 4]>::try_collect((0..).take(usize::max_value()));
+    /// assert_eq!(err.unwrap_err(), CollectionAllocErr::CapacityOverflow);
+    /// ```
+    pub fn try_collect<I: IntoIterator<Item=A::Item>>(iterable: I)
+                                                      -> Result<SmallVec<A>, CollectionAllocErr> {
+        let iter = iterable.into_iter();
+        let mut v = SmallVec::new();
+        match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => v.try_reserve_exact(lower)?,
+            (lower, _) => v.try_reserve(lower)?,
+        }
+        for elem in iter {
+            if v.len() == v.capacity() {
+                v.try_reserve(1)?;
+            }
+            v.push(elem);
+        }
+        Ok(v)
+    }
+
+    /// Append the `Ok` values of an iterator of results, stopping at the first `Err` and
+    /// returning it.
+    ///
+    /// Unlike collecting into a `Result<SmallVec<A>, E>`, the elements appended before the
+    /// error stay in the vector.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1]);
+    /// let res = v.try_extend("2 3 x 5".split(' ').map(|s| s.parse::<u32>()));
+    /// assert!(res.is_err());
+    /// assert_eq!(&*v, &[1, 2, 3]);
+    /// ```
+    pub fn try_extend<I, E>(&mut self, iterable: I) -> Result<(), E>
+        where I: IntoIterator<Item = Result<A::Item, E>>
+    {
+        let iter = iterable.into_iter();
+        self.reserve(iter.size_hint().0);
+        for elem in iter {
+            self.push(elem?);
+        }
+        Ok(())
+    }
+
+    /// Collect an iterator into a new `SmallVec` without ever allocating, as long as all of its
+    /// elements fit inline.
+    ///
+    /// If the iterator yields more than `inline_size()` elements, collection stops after the
+    /// first element that does not fit, and the elements taken so far, including that one, are
+    /// returned as `Err`. Only that error path spills to the heap.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v = SmallVec::<[u8; 4]>::try_from_iter_inline(0..4).unwrap();
+    /// assert!(!v.spilled());
+    ///
+    /// let partial = SmallVec::<[u8; 4]>::try_from_iter_inline(0..10).unwrap_err();
+    /// assert_eq!(&*partial, &[0, 1, 2, 3, 4]);
+    /// ```
+    pub fn try_from_iter_inline<I: IntoIterator<Item=A::Item>>(iterable: I)
+                                                               -> Result<SmallVec<A>, SmallVec<A>> {
+        let mut v = SmallVec::new();
+        for elem in iterable {
+            let full = v.len() == A::size();
+            v.push(elem);
+            if full {
+                return Err(v);
+            }
+        }
+        Ok(v)
+    }
+
+    /// Collect an iterator into a new `SmallVec` with exactly the capacity it needs, by running a
+    /// clone of the iterator to count its elements first.
+    ///
+    /// This allocates at most once, however unreliable the iterator's `size_hint` is, but the
+    /// iterator is cloned and run twice, so it is only worth it for iterators that are cheap to
+    /// clone and to advance, like a filtered range. If the second pass yields more elements than
+    /// the first, the vector grows as with `extend`.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v = SmallVec::<[u32; 2]>::from_iter_exact((0..100).filter(|x| x % 10 == 0));
+    /// assert_eq!(v.len(), 10);
+    /// assert_eq!(v.capacity(), 10);
+    /// ```
+    pub fn from_iter_exact<I: Iterator<Item = A::Item> + Clone>(iter: I) -> Self {
+        let mut v = SmallVec::with_capacity(iter.clone().count());
+        v.extend(iter);
+        v
+    }
+
+    /// Shrink the capacity of the vector as much as possible.
+    ///
+    /// When possible, this will move data from an external heap buffer to the vector's inline
+    /// storage.
+    pub fn shrink_to_fit(&mut self) {
+        infallible(self.try_shrink_to_fit())
+    }
+
+    /// Shrink the capacity of the vector as much as possible, like
+    /// [`shrink_to_fit`](#method.shrink_to_fit), but return an error instead of aborting if the
+    /// smaller heap buffer cannot be allocated. The vector is left unchanged in that case.
+    ///
+    /// Moving the data back to inline storage never allocates, so it cannot fail.
+    pub fn try_shrink_to_fit(&mut self) -> Result<(), CollectionAllocErr> {
+        if !self.spilled() {
+            return Ok(());
+        }
         let len = self.len();
         if self.inline_size() >= len {
             unsafe {
-                let (ptr, len) = self.data.heap();
-                self.data = SmallVecData::from_inline(mem::uninitialized());
-                ptr::copy_nonoverlapping(ptr, self.data.inline_mut().ptr_mut(), len);
-                deallocate(ptr, self.capacity);
+                let (ptr, _, cap) = self.triple_mut();
+                self.data = SmallVecData::inline_uninit();
+                ptr::copy_nonoverlapping(ptr, self.data.inline_mut(), len);
+                deallocate(ptr, cap);
                 self.capacity = len;
             }
         } else if self.capacity() > len {
-            self.grow(len);
+            self.try_set_capacity(len)?;
+        }
+        Ok(())
+    }
+
+    /// Shrink the capacity of the vector with a lower bound.
+    ///
+    /// The capacity will remain at least as large as both the length and `min_capacity`, and
+    /// the data moves back to inline storage if that is large enough. If the current capacity
+    /// is already at most `min_capacity`, this does nothing.
+    ///
+    /// A heap buffer cannot shrink in place, since the allocator has to be given back the size
+    /// it allocated. Shrinking one moves the elements to a new allocation and invalidates
+    /// pointers into the old one.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 2]> = SmallVec::with_capacity(16);
+    /// v.extend(0..3);
+    /// v.shrink_to(8);
+    /// assert_eq!(v.capacity(), 8);
+    /// v.shrink_to(0);
+    /// assert_eq!(v.capacity(), 3);
+    /// v.truncate(1);
+    /// v.shrink_to(0);
+    /// assert!(!v.spilled());
+    /// ```
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        infallible(self.try_shrink_to(min_capacity))
+    }
+
+    /// Shrink the capacity of the vector with a lower bound, like
+    /// [`shrink_to`](#method.shrink_to), but return an error instead of aborting if the smaller
+    /// heap buffer cannot be allocated. The vector is left unchanged in that case.
+    pub fn try_shrink_to(&mut self, min_capacity: usize) -> Result<(), CollectionAllocErr> {
+        let new_cap = cmp::max(self.len(), min_capacity);
+        if new_cap < self.capacity() {
+            self.try_set_capacity(new_cap)?;
+        }
+        Ok(())
+    }
+
+    /// Shrink the capacity of a spilled vector as much as possible without moving the data
+    /// back inline: the new capacity is the larger of the length and `inline_size() + 1`.
+    ///
+    /// A vector that has not spilled is left as it is. As with any shrink, the elements move to
+    /// a smaller heap buffer, so their address may change.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::with_capacity(64);
+    /// v.extend_from_slice(&[1, 2]);
+    /// v.shrink_to_fit_keep_spilled();
+    /// assert!(v.spilled());
+    /// assert_eq!(v.capacity(), 5);
+    /// ```
+    pub fn shrink_to_fit_keep_spilled(&mut self) {
+        if self.spilled() {
+            let new_cap = cmp::max(self.len(), self.inline_size() + 1);
+            if new_cap < self.capacity() {
+                self.set_capacity(new_cap);
+            }
         }
     }
 
@@ -729,6 +1581,13 @@ impl<A: Array> SmallVec<A> {
     pub fn truncate(&mut self, len: usize) {
         unsafe {
             let (ptr, len_ptr, _) = self.triple_mut();
+            if !mem::needs_drop::<A::Item>() {
+                // Nothing to drop, so just update the length.
+                if len < *len_ptr {
+                    *len_ptr = len;
+                }
+                return;
+            }
             while len < *len_ptr {
                 let last_index = *len_ptr - 1;
                 *len_ptr = last_index;
@@ -737,20 +1596,248 @@ impl<A: Array> SmallVec<A> {
         }
     }
 
+    /// Shorten the vector like [`truncate`](#method.truncate), but drop the removed elements in
+    /// order from front to back instead of from the back inward.
+    ///
+    /// The length is updated before any element is dropped, so a panicking destructor never
+    /// causes an element to be dropped twice.
+    pub fn truncate_front_to_back(&mut self, len: usize) {
+        unsafe {
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let old_len = *len_ptr;
+            if len >= old_len {
+                return;
+            }
+            *len_ptr = len;
+            let tail = slice::from_raw_parts_mut(ptr.offset(len as isize), old_len - len);
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    /// Shorten the vector like [`truncate`](#method.truncate), but return the removed elements
+    /// as an iterator instead of dropping them.
+    ///
+    /// This is `drain(len..)`, except that a `len` greater than or equal to the vector's length
+    /// returns an empty iterator instead of panicking. The vector is truncated right away;
+    /// removed elements that are not consumed are dropped along with the iterator.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3, 4, 5]);
+    /// assert_eq!(v.truncate_drain(2).sum::<u8>(), 12);
+    /// assert_eq!(&*v, &[1, 2]);
+    /// assert_eq!(v.truncate_drain(5).count(), 0);
+    /// ```
+    pub fn truncate_drain(&mut self, len: usize) -> Drain<'_, A::Item> {
+        let len = cmp::min(len, self.len());
+        self.drain(len..)
+    }
+
     /// Extracts a slice containing the entire vector.
     ///
+    /// The elements of a `SmallVec` are always stored contiguously, whether inline or on the
+    /// heap, so unlike `VecDeque` no `make_contiguous` step is ever needed.
+    ///
     /// Equivalent to `&s[..]`.
+    #[inline]
     pub fn as_slice(&self) -> &[A::Item] {
         self
     }
 
     /// Extracts a mutable slice of the entire vector.
     ///
-    /// Equivalent to `&mut s[..]`.
+    /// Equivalent to `&mut s[..]`. Finding the buffer means checking whether the vector has
+    /// spilled, and the compiler generally cannot move that check out of a loop that writes
+    /// through the slice, since it cannot tell that the writes leave the vector's own fields
+    /// alone. In hot loops, take the slice once and index into it:
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u32; 4]> = SmallVec::from_elem(0, 100);
+    /// let slice = v.as_mut_slice();
+    /// for i in 0..slice.len() {
+    ///     slice[i] = i as u32 * 2;
+    /// }
+    /// assert_eq!(v[50], 100);
+    /// ```
+    #[inline]
     pub fn as_mut_slice(&mut self) -> &mut [A::Item] {
         self
     }
 
+    /// Returns the two raw pointers spanning the vector's elements, like the slice method of the
+    /// same name.
+    ///
+    /// The range is invalidated by anything that may reallocate the vector or move it between
+    /// inline and heap storage, and for inline storage by moving the vector itself.
+    #[inline]
+    pub fn as_ptr_range(&self) -> ops::Range<*const A::Item> {
+        let (ptr, len, _) = self.triple();
+        unsafe { ptr..ptr.offset(len as isize) }
+    }
+
+    /// Returns the two unsafe mutable pointers spanning the vector's elements, like the slice
+    /// method of the same name.
+    ///
+    /// The same invalidation rules as for [`as_ptr_range`](#method.as_ptr_range) apply.
+    #[inline]
+    pub fn as_mut_ptr_range(&mut self) -> ops::Range<*mut A::Item> {
+        let (ptr, &mut len, _) = self.triple_mut();
+        unsafe { ptr..ptr.offset(len as isize) }
+    }
+
+    /// Returns an iterator over overlapping windows of `N` elements, as arrays.
+    ///
+    /// Like the slice method of the same name; if the vector is shorter than `N`, the iterator
+    /// is empty. Panics if `N` is 0.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v: SmallVec<[f64; 4]> = SmallVec::from_slice(&[1., 2., 4., 8.]);
+    /// let diffs: Vec<f64> = v.array_windows().map(|&[a, b]| b - a).collect();
+    /// assert_eq!(diffs, &[1., 2., 4.]);
+    /// ```
+    #[inline]
+    pub fn array_windows<const N: usize>(&self) -> impl Iterator<Item = &[A::Item; N]> {
+        assert!(N != 0, "window size must be non-zero");
+        self.windows(N).map(|w| <&[A::Item; N]>::try_from(w).unwrap())
+    }
+
+    /// Returns an iterator over mutable chunks of `chunk_size` elements, like the slice method
+    /// of the same name. The last chunk is shorter if `chunk_size` does not divide the length.
+    ///
+    /// The elements are always contiguous, so this works the same whether or not the vector
+    /// has spilled. Panics if `chunk_size` is 0.
+    #[inline]
+    pub fn chunks_mut(&mut self, chunk_size: usize) -> slice::ChunksMut<'_, A::Item> {
+        self.as_mut_slice().chunks_mut(chunk_size)
+    }
+
+    /// Returns an iterator over mutable chunks of exactly `chunk_size` elements, like the slice
+    /// method of the same name. The leftover elements are available from the iterator's
+    /// `into_remainder`.
+    ///
+    /// The elements are always contiguous, so this works the same whether or not the vector
+    /// has spilled. Panics if `chunk_size` is 0.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3, 4, 5]);
+    /// let mut chunks = v.chunks_exact_mut(2);
+    /// for chunk in &mut chunks {
+    ///     chunk.swap(0, 1);
+    /// }
+    /// chunks.into_remainder()[0] = 0;
+    /// assert_eq!(&*v, &[2, 1, 4, 3, 0]);
+    /// ```
+    #[inline]
+    pub fn chunks_exact_mut(&mut self, chunk_size: usize) -> slice::ChunksExactMut<'_, A::Item> {
+        self.as_mut_slice().chunks_exact_mut(chunk_size)
+    }
+
+    /// Reverses the order of the elements in place.
+    ///
+    /// This forwards to the slice method and works the same whether or not the vector has
+    /// spilled; it never allocates or changes the capacity.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+    /// v.reverse();
+    /// assert_eq!(&*v, &[3, 2, 1]);
+    /// ```
+    #[inline]
+    pub fn reverse(&mut self) {
+        self.as_mut_slice().reverse()
+    }
+
+    /// Returns the initialized elements of the vector together with its remaining spare
+    /// capacity as a slice of `MaybeUninit<A::Item>`.
+    ///
+    /// Elements written to the spare capacity are not part of the vector until they are
+    /// committed with [`set_len`](#method.set_len). Calling `set_len(len + n)` is only sound
+    /// once the first `n` spare slots have been initialized.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2]);
+    /// let len = v.len();
+    /// {
+    ///     let (init, spare) = v.split_at_spare_mut();
+    ///     let sum = init.iter().sum();
+    ///     spare[0] = std::mem::MaybeUninit::new(sum);
+    /// }
+    /// unsafe { v.set_len(len + 1) };
+    /// assert_eq!(&*v, &[1, 2, 3]);
+    /// ```
+    pub fn split_at_spare_mut(&mut self) -> (&mut [A::Item], &mut [MaybeUninit<A::Item>]) {
+        unsafe {
+            let (ptr, &mut len, cap) = self.triple_mut();
+            let spare_ptr = ptr.offset(len as isize) as *mut MaybeUninit<A::Item>;
+            (slice::from_raw_parts_mut(ptr, len),
+             slice::from_raw_parts_mut(spare_ptr, cap - len))
+        }
+    }
+
+    /// Returns mutable references to several elements at once.
+    ///
+    /// Returns `None` if any index is out of bounds or if the same index appears more than once.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+    /// if let Some([a, b]) = v.get_disjoint_mut([0, 2]) {
+    ///     std::mem::swap(a, b);
+    /// }
+    /// assert_eq!(&*v, &[3, 2, 1]);
+    /// assert!(v.get_disjoint_mut([1, 1]).is_none());
+    /// assert!(v.get_disjoint_mut([0, 3]).is_none());
+    /// ```
+    pub fn get_disjoint_mut<const N: usize>(&mut self, indices: [usize; N])
+                                            -> Option<[&mut A::Item; N]> {
+        let (ptr, &mut len, _) = self.triple_mut();
+        for (i, &index) in indices.iter().enumerate() {
+            if index >= len || indices[..i].contains(&index) {
+                return None;
+            }
+        }
+        Some(indices.map(|index| unsafe { &mut *ptr.offset(index as isize) }))
+    }
+
+    /// Extend the vector with the contents of an iterator and return the number of elements
+    /// that were appended.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2]);
+    /// assert_eq!(v.extend_count(3..6), 3);
+    /// assert_eq!(&*v, &[1, 2, 3, 4, 5]);
+    /// ```
+    pub fn extend_count<I: IntoIterator<Item=A::Item>>(&mut self, iterable: I) -> usize {
+        let old_len = self.len();
+        self.extend(iterable);
+        self.len() - old_len
+    }
+
+    /// Divides the vector into two mutable slices at an index.
+    ///
+    /// The first slice contains the elements `[0, mid)` and the second `[mid, len)`. This works
+    /// the same whether the data is stored inline or has spilled onto the heap.
+    ///
+    /// Panics if `mid > len`.
+    #[inline]
+    pub fn split_at_mut(&mut self, mid: usize) -> (&mut [A::Item], &mut [A::Item]) {
+        self.as_mut_slice().split_at_mut(mid)
+    }
+
     /// Remove the element at position `index`, replacing it with the last element.
     ///
     /// This does not preserve ordering, but is O(1).
@@ -763,7 +1850,35 @@ impl<A: Array> SmallVec<A> {
         unsafe { self.pop().unchecked_unwrap() }
     }
 
+    /// Like [`swap_remove`](#method.swap_remove), but also return the index the last element
+    /// was moved from: `Some(len - 1)` if it was moved into `index`, or `None` if the removed
+    /// element was itself the last one.
+    ///
+    /// This is what is needed to fix up a table of indices into the vector after removing.
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[char; 4]> = SmallVec::from_slice(&['a', 'b', 'c', 'd']);
+    /// assert_eq!(v.swap_remove_indexed(1), ('b', Some(3)));
+    /// assert_eq!(&*v, &['a', 'd', 'c']);
+    /// assert_eq!(v.swap_remove_indexed(2), ('c', None));
+    /// assert_eq!(&*v, &['a', 'd']);
+    /// ```
+    #[inline]
+    pub fn swap_remove_indexed(&mut self, index: usize) -> (A::Item, Option<usize>) {
+        let len = self.len();
+        let removed = self.swap_remove(index);
+        let moved_from = if index == len - 1 { None } else { Some(len - 1) };
+        (removed, moved_from)
+    }
+
     /// Remove all elements from the vector.
+    ///
+    /// The capacity is left unchanged. For element types that do not need to be dropped, such as
+    /// `u8`, this is O(1): only the length is reset.
     #[inline]
     pub fn clear(&mut self) {
         self.truncate(0);
@@ -790,12 +1905,19 @@ impl<A: Array> SmallVec<A> {
     ///
     /// Panics if `index` is out of bounds.
     pub fn insert(&mut self, index: usize, element: A::Item) {
-        self.reserve(1);
-
         unsafe {
-            let (mut ptr, len_ptr, _) = self.triple_mut();
+            let (mut ptr, mut len_ptr, cap) = self.triple_mut();
             let len = *len_ptr;
             assert!(index <= len);
+            if len == cap {
+                // Growing moves the buffer, so only then are the pointers fetched again.
+                self.reserve(1);
+                let (new_ptr, new_len_ptr, _) = self.triple_mut();
+                ptr = new_ptr;
+                len_ptr = new_len_ptr;
+            }
+            // As in `push`, only zero-sized items can get past `isize::MAX`.
+            debug_assert!(mem::size_of::<A::Item>() == 0 || len < isize::MAX as usize);
             *len_ptr = len + 1;
             ptr = ptr.offset(index as isize);
             ptr::copy(ptr, ptr.offset(1), len - index);
@@ -803,10 +1925,20 @@ impl<A: Array> SmallVec<A> {
         }
     }
 
+    /// Insert multiple elements at position `index`, shifting all following elements toward the
+    /// back.
+    ///
+    /// This is the same as [`insert_many`](#method.insert_many), under a name that makes the
+    /// batch nature of the operation explicit.
+    #[inline]
+    pub fn insert_from_iter<I: IntoIterator<Item=A::Item>>(&mut self, index: usize, iterable: I) {
+        self.insert_many(index, iterable)
+    }
+
     /// Insert multiple elements at position `index`, shifting all following elements toward the
     /// back.
     pub fn insert_many<I: IntoIterator<Item=A::Item>>(&mut self, index: usize, iterable: I) {
-        let iter = iterable.into_iter();
+        let mut iter = iterable.into_iter();
         if index == self.len() {
             return self.extend(iter);
         }
@@ -816,46 +1948,65 @@ impl<A: Array> SmallVec<A> {
         assert!(index + lower_size_bound >= index);  // Protect against overflow
         self.reserve(lower_size_bound);
 
+        let mut num_added = 0;
         unsafe {
-            let old_len = self.len();
+            // Take the pointer from `triple_mut` rather than `as_mut_ptr`: the latter goes
+            // through a slice of only `len` elements and may not be used to write past it.
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let old_len = *len_ptr;
             assert!(index <= old_len);
-            let mut ptr = self.as_mut_ptr().offset(index as isize);
+            let ptr = ptr.offset(index as isize);
 
             // Move the trailing elements.
             ptr::copy(ptr, ptr.offset(lower_size_bound as isize), old_len - index);
 
             // In case the iterator panics, don't double-drop the items we just copied above.
-            self.set_len(index);
-
-            let mut num_added = 0;
-            for element in iter {
-                let mut cur = ptr.offset(num_added as isize);
-                if num_added >= lower_size_bound {
-                    // Iterator provided more elements than the hint.  Move trailing items again.
-                    self.reserve(1);
-                    ptr = self.as_mut_ptr().offset(index as isize);
-                    cur = ptr.offset(num_added as isize);
-                    ptr::copy(cur, cur.offset(1), old_len - index);
+            // Write through `len_ptr` so that `self` is not borrowed again while `ptr` is live.
+            *len_ptr = index;
+
+            // Fill the gap opened for the hinted elements first. For an exact-size iterator this
+            // is the only loop that runs, so the tail is moved exactly once.
+            while num_added < lower_size_bound {
+                match iter.next() {
+                    Some(element) => {
+                        ptr::write(ptr.offset(num_added as isize), element);
+                        num_added += 1;
+                    }
+                    None => break,
                 }
-                ptr::write(cur, element);
-                num_added += 1;
             }
             if num_added < lower_size_bound {
                 // Iterator provided fewer elements than the hint
                 ptr::copy(ptr.offset(lower_size_bound as isize), ptr.offset(num_added as isize), old_len - index);
+                self.set_len(old_len + num_added);
+                return;
             }
 
             self.set_len(old_len + num_added);
         }
+
+        // The iterator may still provide more elements than the hint. `self` is whole again at
+        // this point, so collect them first and move the trailing elements only once more.
+        let rest: SmallVec<A> = iter.collect();
+        if !rest.is_empty() {
+            self.insert_many(index + num_added, rest);
+        }
     }
 
     /// Convert a SmallVec to a Vec, without reallocating if the SmallVec has already spilled onto
     /// the heap.
+    ///
+    /// A spilled vector hands its heap buffer over to the `Vec` as is. Inline data has to be
+    /// moved into a new allocation. See [`from_vec`](#method.from_vec) for when converting back
+    /// keeps the allocation.
+    ///
+    /// With the `capacity_header` feature, the heap buffer of a spilled vector cannot be handed
+    /// over either, so the elements are always moved into a new allocation.
     pub fn into_vec(self) -> Vec<A::Item> {
-        if self.spilled() {
+        if self.spilled() && !cfg!(all(feature = "capacity_header", not(feature = "union"))) {
             unsafe {
-                let (ptr, len) = self.data.heap();
-                let v = Vec::from_raw_parts(ptr, len, self.capacity);
+                let (ptr, len, cap) = self.triple();
+                let v = Vec::from_raw_parts(ptr as *mut A::Item, len, cap);
                 mem::forget(self);
                 v
             }
@@ -886,27 +2037,176 @@ impl<A: Array> SmallVec<A> {
     /// This method operates in place and preserves the order of the retained
     /// elements.
     pub fn retain<F: FnMut(&mut A::Item) -> bool>(&mut self, mut f: F) {
-        let mut del = 0;
-        let len = self.len();
-        for i in 0..len {
-            if !f(&mut self[i]) {
-                del += 1;
-            } else if del > 0 {
-                self.swap(i - del, i);
-            }
-        }
-        self.truncate(len - del);
+        self.retain_with_index(|_, e| f(e))
     }
 
-    /// Removes consecutive duplicate elements.
-    pub fn dedup(&mut self) where A::Item: PartialEq<A::Item> {
-        self.dedup_by(|a, b| a == b);
+    /// Retains only the elements specified by the predicate, dropping the others in place, and
+    /// returns how many elements were removed.
+    ///
+    /// The capacity is left unchanged; see
+    /// [`retain_drain_and_shrink`](#method.retain_drain_and_shrink) to also move the remaining
+    /// elements back inline.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u32; 4]> = (0..10).collect();
+    /// assert_eq!(v.retain_drain(|&x| x % 4 == 0), 7);
+    /// assert_eq!(&*v, &[0, 4, 8]);
+    /// assert!(v.spilled());
+    /// ```
+    pub fn retain_drain<F: FnMut(&A::Item) -> bool>(&mut self, mut f: F) -> usize {
+        let old_len = self.len();
+        self.retain(|e| f(e));
+        old_len - self.len()
     }
 
-    /// Removes consecutive duplicate elements using the given equality relation.
-    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
-        where F: FnMut(&mut A::Item, &mut A::Item) -> bool
-    {
+    /// Like [`retain_drain`](#method.retain_drain), but if the vector had spilled and the
+    /// remaining elements fit inline, they are moved back into the inline buffer and the heap
+    /// allocation is freed. Otherwise the allocation is kept.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u32; 4]> = (0..10).collect();
+    /// assert_eq!(v.retain_drain_and_shrink(|&x| x % 4 == 0), 7);
+    /// assert_eq!(&*v, &[0, 4, 8]);
+    /// assert!(!v.spilled());
+    /// ```
+    pub fn retain_drain_and_shrink<F: FnMut(&A::Item) -> bool>(&mut self, f: F) -> usize {
+        let removed = self.retain_drain(f);
+        if self.spilled() && self.len() <= A::size() {
+            self.shrink_to_fit();
+        }
+        removed
+    }
+
+    /// Retains only the elements specified by the predicate, like [`retain`](#method.retain),
+    /// and returns whether any element was removed.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+    /// assert!(!v.retain_changed(|&x| x < 10));
+    /// assert!(v.retain_changed(|&x| x != 2));
+    /// assert_eq!(&*v, &[1, 3]);
+    /// ```
+    pub fn retain_changed<F: FnMut(&A::Item) -> bool>(&mut self, mut f: F) -> bool {
+        let old_len = self.len();
+        self.retain(|e| f(e));
+        self.len() != old_len
+    }
+
+    /// Retains only the elements specified by the predicate, which is also passed the index
+    /// of each element.
+    ///
+    /// The index is the element's position in the vector before any removals.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[10, 11, 12, 13, 14]);
+    /// v.retain_with_index(|i, _| i % 2 == 0);
+    /// assert_eq!(&*v, &[10, 12, 14]);
+    /// ```
+    pub fn retain_with_index<F: FnMut(usize, &mut A::Item) -> bool>(&mut self, mut f: F) {
+        let mut del = 0;
+        let len = self.len();
+        for i in 0..len {
+            if !f(i, &mut self[i]) {
+                del += 1;
+            } else if del > 0 {
+                self.swap(i - del, i);
+            }
+        }
+        self.truncate(len - del);
+    }
+
+    /// Retains only the elements specified by the predicate, which is also passed the number of
+    /// elements kept so far.
+    ///
+    /// This makes it possible to cap the number of survivors in the same pass, without a separate
+    /// counter or a `truncate` afterwards.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// // Keep at most two odd numbers.
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3, 4, 5, 7]);
+    /// v.retain_bounded(|kept, &x| kept < 2 && x % 2 == 1);
+    /// assert_eq!(&*v, &[1, 3]);
+    /// ```
+    pub fn retain_bounded<F: FnMut(usize, &A::Item) -> bool>(&mut self, mut f: F) {
+        let mut del = 0;
+        let len = self.len();
+        for i in 0..len {
+            if !f(i - del, &self[i]) {
+                del += 1;
+            } else if del > 0 {
+                self.swap(i - del, i);
+            }
+        }
+        self.truncate(len - del);
+    }
+
+    /// Replaces every element with the result of applying `f` to it, in place.
+    ///
+    /// If `f` panics, the elements already transformed are kept, and the element being
+    /// transformed as well as all those after it are dropped.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[String; 2]> = SmallVec::new();
+    /// v.extend(["a", "b", "c"].iter().map(|s| s.to_string()));
+    /// v.map_in_place(|s| s + "!");
+    /// assert_eq!(&*v, &["a!", "b!", "c!"]);
+    /// ```
+    pub fn map_in_place<F: FnMut(A::Item) -> A::Item>(&mut self, mut f: F) {
+        // While `f` runs, the slot at `index` has been moved out of. On unwind, keep the
+        // transformed prefix and drop the untouched tail past the gap.
+        struct Guard<'a, T: 'a> {
+            ptr: *mut T,
+            len_ptr: &'a mut usize,
+            index: usize,
+            len: usize,
+        }
+
+        impl<'a, T: 'a> Drop for Guard<'a, T> {
+            fn drop(&mut self) {
+                *self.len_ptr = self.index;
+                if self.index < self.len {
+                    unsafe {
+                        let tail = self.ptr.offset(self.index as isize + 1);
+                        let tail_len = self.len - self.index - 1;
+                        ptr::drop_in_place(slice::from_raw_parts_mut(tail, tail_len));
+                    }
+                }
+            }
+        }
+
+        unsafe {
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let len = *len_ptr;
+            let mut guard = Guard { ptr, len_ptr, index: 0, len };
+            while guard.index < len {
+                let slot = ptr.offset(guard.index as isize);
+                ptr::write(slot, f(ptr::read(slot)));
+                guard.index += 1;
+            }
+        }
+    }
+
+    /// Removes consecutive duplicate elements.
+    pub fn dedup(&mut self) where A::Item: PartialEq<A::Item> {
+        self.dedup_by(|a, b| a == b);
+    }
+
+    /// Removes consecutive duplicate elements using the given equality relation.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+        where F: FnMut(&mut A::Item, &mut A::Item) -> bool
+    {
         // See the implementation of Vec::dedup_by in the
         // standard library for an explanation of this algorithm.
         let len = self.len();
@@ -934,6 +2234,130 @@ impl<A: Array> SmallVec<A> {
         self.truncate(w);
     }
 
+    /// Binary searches this sorted vector for a given element.
+    ///
+    /// See [`slice::binary_search`](https://doc.rust-lang.org/std/primitive.slice.html#method.binary_search).
+    #[inline]
+    pub fn binary_search(&self, x: &A::Item) -> Result<usize, usize> where A::Item: Ord {
+        self.as_slice().binary_search(x)
+    }
+
+    /// Binary searches this sorted vector with a comparator function.
+    ///
+    /// See [`slice::binary_search_by`](https://doc.rust-lang.org/std/primitive.slice.html#method.binary_search_by).
+    #[inline]
+    pub fn binary_search_by<'a, F>(&'a self, f: F) -> Result<usize, usize>
+        where F: FnMut(&'a A::Item) -> cmp::Ordering
+    {
+        self.as_slice().binary_search_by(f)
+    }
+
+    /// Binary searches this sorted vector with a key extraction function.
+    ///
+    /// See [`slice::binary_search_by_key`](https://doc.rust-lang.org/std/primitive.slice.html#method.binary_search_by_key).
+    #[inline]
+    pub fn binary_search_by_key<'a, B, F>(&'a self, b: &B, f: F) -> Result<usize, usize>
+        where F: FnMut(&'a A::Item) -> B,
+              B: Ord
+    {
+        self.as_slice().binary_search_by_key(b, f)
+    }
+
+    /// Returns `true` if the iterator yields exactly the elements of the vector, in order.
+    ///
+    /// This compares against a streaming source without collecting it first. It stops at the
+    /// first mismatch; if all elements match, one more item is taken from the iterator to check
+    /// that it is not longer than the vector.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+    /// assert!(v.eq_iter(1..4));
+    /// assert!(!v.eq_iter(1..3));
+    /// assert!(!v.eq_iter(1..5));
+    /// ```
+    pub fn eq_iter<I: IntoIterator<Item = A::Item>>(&self, iter: I) -> bool
+        where A::Item: PartialEq
+    {
+        let mut iter = iter.into_iter();
+        for x in self.iter() {
+            match iter.next() {
+                Some(ref y) if x == y => {}
+                _ => return false,
+            }
+        }
+        iter.next().is_none()
+    }
+
+    /// Sorts the vector and removes all duplicate elements, leaving a compact set of unique
+    /// elements in ascending order.
+    ///
+    /// If the unique elements fit in the inline buffer, a heap allocation is given up and the
+    /// data moved back inline. Which of several equal elements is kept is unspecified.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[3, 1, 3, 2, 1, 3]);
+    /// assert!(v.spilled());
+    /// v.sort_unique();
+    /// assert_eq!(&*v, &[1, 2, 3]);
+    /// assert!(!v.spilled());
+    /// ```
+    pub fn sort_unique(&mut self) where A::Item: Ord {
+        self.sort_unstable();
+        self.dedup();
+        if self.spilled() && self.len() <= self.inline_size() {
+            self.shrink_to_fit();
+        }
+    }
+
+    /// Removes all duplicate elements, keeping the first occurrence of each and preserving
+    /// their order.
+    ///
+    /// Unlike [`dedup`](#method.dedup), the duplicates do not have to be consecutive, and unlike
+    /// [`sort_unique`](#method.sort_unique), the order is kept. Short vectors are scanned
+    /// directly without allocating; longer ones track the elements seen in a `HashSet` when
+    /// the `std` feature is enabled.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[3, 1, 3, 2, 1, 3]);
+    /// v.unique();
+    /// assert_eq!(&*v, &[3, 1, 2]);
+    /// ```
+    pub fn unique(&mut self) where A::Item: Hash + Eq {
+        let len = self.len();
+        #[cfg(feature = "std")]
+        {
+            // Below this length a quadratic scan beats building a hash set.
+            const SCAN_LEN: usize = 32;
+
+            if len > SCAN_LEN {
+                use std::collections::HashSet;
+
+                let keep: Vec<bool> = {
+                    let mut seen = HashSet::with_capacity(len);
+                    self.iter().map(|x| seen.insert(x)).collect()
+                };
+                self.retain_with_index(|i, _| keep[i]);
+                return;
+            }
+        }
+
+        // The first `w` elements are the unique ones seen so far, in order.
+        let mut w = 0;
+        for r in 0..len {
+            if !self[..w].contains(&self[r]) {
+                self.swap(r, w);
+                w += 1;
+            }
+        }
+        self.truncate(w);
+    }
+
     /// Removes consecutive elements that map to the same key.
     pub fn dedup_by_key<F, K>(&mut self, mut key: F)
         where F: FnMut(&mut A::Item) -> K,
@@ -965,6 +2389,10 @@ impl<A: Array> SmallVec<A> {
     /// Additionally, `capacity` must be greater than the amount of inline
     /// storage `A` has; that is, the new `SmallVec` must need to spill over
     /// into heap allocated storage. This condition is asserted against.
+    /// In debug builds, the length and the alignment of `ptr` are checked as well.
+    ///
+    /// With the `capacity_header` feature, the buffer of a `Vec` never qualifies: `ptr` must
+    /// come from a spilled `SmallVec` of the same item type.
     ///
     /// The ownership of `ptr` is effectively transferred to the
     /// `SmallVec` which may then deallocate, reallocate or change the
@@ -1016,10 +2444,39 @@ impl<A: Array> SmallVec<A> {
         capacity: usize,
     ) -> SmallVec<A> {
         assert!(capacity > A::size());
-        SmallVec {
-            capacity,
-            data: SmallVecData::from_heap(ptr, length),
-        }
+        debug_assert!(length <= capacity, "length exceeds capacity");
+        debug_assert!(ptr as usize % mem::align_of::<A::Item>() == 0, "misaligned pointer");
+        let mut v = SmallVec::new();
+        v.set_heap(ptr, length, capacity);
+        v
+    }
+}
+
+impl<T, const N: usize> SmallVec<[T; N]> where [T; N]: Array<Item = T> {
+    /// Constructs an inline `SmallVec` from a buffer and a length in a `const` context, like
+    /// [`from_buf_and_len`](#method.from_buf_and_len).
+    ///
+    /// If `len` is greater than `N`, this fails to compile when evaluated in a `const` or
+    /// `static` initializer, and panics otherwise. As with `from_buf_and_len`, the elements
+    /// past `len` are forgotten.
+    ///
+    /// ```rust
+    /// use smallvec::SmallVec;
+    ///
+    /// static TABLE: SmallVec<[u32; 8]> = SmallVec::from_const_buf([1, 2, 3, 4, 0, 0, 0, 0], 4);
+    ///
+    /// assert_eq!(&*TABLE, &[1, 2, 3, 4]);
+    /// ```
+    ///
+    /// ```compile_fail
+    /// use smallvec::SmallVec;
+    ///
+    /// static TOO_LONG: SmallVec<[u32; 2]> = SmallVec::from_const_buf([1, 2], 3);
+    /// ```
+    #[inline]
+    pub const fn from_const_buf(buf: [T; N], len: usize) -> SmallVec<[T; N]> {
+        assert!(len <= N, "length exceeds the inline capacity");
+        unsafe { SmallVec::from_buf_and_len_unchecked(buf, len) }
     }
 }
 
@@ -1030,22 +2487,21 @@ impl<A: Array> SmallVec<A> where A::Item: Copy {
     pub fn from_slice(slice: &[A::Item]) -> Self {
         let len = slice.len();
         if len <= A::size() {
+            let mut data = SmallVecData::<A>::inline_uninit();
+            unsafe { ptr::copy_nonoverlapping(slice.as_ptr(), data.inline_mut(), len) };
             SmallVec {
                 capacity: len,
-                data: SmallVecData::from_inline(unsafe {
-                    let mut data: A = mem::uninitialized();
-                    ptr::copy_nonoverlapping(slice.as_ptr(), data.ptr_mut(), len);
-                    data
-                })
+                data,
             }
         } else {
-            let mut b = slice.to_vec();
-            let (ptr, cap) = (b.as_mut_ptr(), b.capacity());
-            mem::forget(b);
-            SmallVec {
-                capacity: cap,
-                data: SmallVecData::from_heap(ptr, len),
+            let mut v = SmallVec::with_capacity(len);
+            unsafe {
+                // Not `as_mut_ptr`, which goes through an empty slice and may not be written.
+                let (ptr, len_ptr, _) = v.triple_mut();
+                ptr::copy_nonoverlapping(slice.as_ptr(), ptr, len);
+                *len_ptr = len;
             }
+            v
         }
     }
 
@@ -1061,10 +2517,11 @@ impl<A: Array> SmallVec<A> where A::Item: Copy {
 
         unsafe {
             let slice_ptr = slice.as_ptr();
-            let ptr = self.as_mut_ptr().offset(index as isize);
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let ptr = ptr.offset(index as isize);
             ptr::copy(ptr, ptr.offset(slice.len() as isize), len - index);
             ptr::copy_nonoverlapping(slice_ptr, ptr, slice.len());
-            self.set_len(len + slice.len());
+            *len_ptr = len + slice.len();
         }
     }
 
@@ -1073,91 +2530,498 @@ impl<A: Array> SmallVec<A> where A::Item: Copy {
     /// For slices of `Copy` types, this is more efficient than `extend`.
     #[inline]
     pub fn extend_from_slice(&mut self, slice: &[A::Item]) {
-        let len = self.len();
-        self.insert_from_slice(len, slice);
+        self.reserve(slice.len());
+        unsafe {
+            // Appending never has a tail to move, unlike `insert_from_slice`.
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let len = *len_ptr;
+            ptr::copy_nonoverlapping(slice.as_ptr(), ptr.offset(len as isize), slice.len());
+            *len_ptr = len + slice.len();
+        }
     }
-}
 
-impl<A: Array> SmallVec<A> where A::Item: Clone {
-    /// Resizes the vector so that its length is equal to `len`.
+    /// Copy elements from a slice and append them to the vector, like
+    /// [`extend_from_slice`](#method.extend_from_slice), and return the newly appended
+    /// elements.
     ///
-    /// If `len` is less than the current length, the vector simply truncated.
+    /// The returned slice borrows the vector mutably, so it is always valid for the vector's
+    /// current buffer, even if appending spilled or reallocated it.
     ///
-    /// If `len` is greater than the current length, `value` is appended to the
-    /// vector until its length equals `len`.
-    pub fn resize(&mut self, len: usize, value: A::Item) {
-        let old_len = self.len();
-
-        if len > old_len {
-            self.extend(repeat(value).take(len - old_len));
-        } else {
-            self.truncate(len);
-        }
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(b"ab");
+    /// v.extend_from_slice_mut(b"cde").make_ascii_uppercase();
+    /// assert_eq!(&*v, b"abCDE");
+    /// ```
+    pub fn extend_from_slice_mut(&mut self, slice: &[A::Item]) -> &mut [A::Item] {
+        let len = self.len();
+        self.extend_from_slice(slice);
+        &mut self[len..]
     }
 
-    /// Creates a `SmallVec` with `n` copies of `elem`.
+    /// Append the elements of a borrowed or owned slice to the vector.
+    ///
+    /// Borrowed data is copied in with [`extend_from_slice`](#method.extend_from_slice). Owned
+    /// data is too, except when the vector is empty and inline and the `Vec` has more capacity
+    /// than the inline buffer: the vector then takes over its allocation as with
+    /// [`from_vec`](#method.from_vec), without copying.
+    ///
     /// ```
     /// use smallvec::SmallVec;
+    /// use std::borrow::Cow;
     ///
-    /// let v = SmallVec::<[char; 128]>::from_elem('d', 2);
-    /// assert_eq!(v, SmallVec::from_buf(['d', 'd']));
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::new();
+    /// v.extend_from_cow(Cow::Borrowed(&b"ab"[..]));
+    /// v.extend_from_cow(Cow::Owned(b"cde".to_vec()));
+    /// assert_eq!(&*v, b"abcde");
     /// ```
-    pub fn from_elem(elem: A::Item, n: usize) -> Self {
-        if n > A::size() {
-            vec![elem; n].into()
-        } else {
-            let mut v = SmallVec::<A>::new();
-            unsafe {
-                let (ptr, len_ptr, _) = v.triple_mut();
-                let mut local_len = SetLenOnDrop::new(len_ptr);
-
-                for i in 0..n as isize {
-                    ::std::ptr::write(ptr.offset(i), elem.clone());
-                    local_len.increment_len(1);
+    pub fn extend_from_cow(&mut self, data: Cow<[A::Item]>) {
+        match data {
+            Cow::Owned(vec) => {
+                if self.is_empty() && !self.spilled() && vec.capacity() > self.inline_size() {
+                    *self = SmallVec::from_vec(vec);
+                } else {
+                    self.extend_from_slice(&vec);
                 }
             }
-            v
+            Cow::Borrowed(slice) => self.extend_from_slice(slice),
         }
     }
-}
 
-impl<A: Array> ops::Deref for SmallVec<A> {
-    type Target = [A::Item];
-    #[inline]
-    fn deref(&self) -> &[A::Item] {
+    /// Move up to `out.len()` elements from the front of the vector into `out`, shifting the
+    /// remaining elements to the front, and return how many were moved.
+    ///
+    /// The elements are copied in bulk rather than read one by one as with `drain`. The
+    /// allocation is kept.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(b"hello");
+    /// let mut out = [0; 2];
+    /// assert_eq!(v.drain_into_slice(&mut out), 2);
+    /// assert_eq!(&out, b"he");
+    /// assert_eq!(&*v, b"llo");
+    /// ```
+    pub fn drain_into_slice(&mut self, out: &mut [A::Item]) -> usize {
         unsafe {
-            let (ptr, len, _) = self.triple();
-            slice::from_raw_parts(ptr, len)
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let len = *len_ptr;
+            let n = cmp::min(out.len(), len);
+            ptr::copy_nonoverlapping(ptr, out.as_mut_ptr(), n);
+            ptr::copy(ptr.offset(n as isize), ptr, len - n);
+            *len_ptr = len - n;
+            n
         }
     }
-}
 
-impl<A: Array> ops::DerefMut for SmallVec<A> {
-    #[inline]
-    fn deref_mut(&mut self) -> &mut [A::Item] {
-        unsafe {
-            let (ptr, &mut len, _) = self.triple_mut();
-            slice::from_raw_parts_mut(ptr, len)
-        }
+    /// Overwrite the elements in `range` with `replacement`, which must have the same length.
+    ///
+    /// Unlike a general splice, nothing is shifted and the vector never reallocates.
+    ///
+    /// Panics if the range is out of bounds or if its length differs from `replacement.len()`.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 8]> = SmallVec::from_slice(b"len=????;");
+    /// v.replace_range_copy(4..8, b"0042");
+    /// assert_eq!(&*v, b"len=0042;");
+    /// ```
+    pub fn replace_range_copy<R: RangeBounds<usize>>(&mut self, range: R,
+                                                     replacement: &[A::Item]) {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        let dst = &mut self.as_mut_slice()[bounds];
+        assert_eq!(dst.len(), replacement.len(),
+                   "replacement length does not match the length of the range");
+        dst.copy_from_slice(replacement);
     }
-}
 
-impl<A: Array> AsRef<[A::Item]> for SmallVec<A> {
-    #[inline]
-    fn as_ref(&self) -> &[A::Item] {
-        self
+    /// Split the vector into a slice of `C`-element arrays, starting at the beginning, and a
+    /// remainder slice with length strictly less than `C`.
+    ///
+    /// `[A::Item; C]` has the same alignment as `A::Item`, so the chunks are exactly as aligned
+    /// as the vector's elements, whether they are stored inline or on the heap.
+    ///
+    /// Panics if `C` is 0.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v: SmallVec<[f32; 8]> = SmallVec::from_slice(&[1., 2., 3., 4., 5.]);
+    /// let (chunks, remainder) = v.as_chunks::<2>();
+    /// assert_eq!(chunks, &[[1., 2.], [3., 4.]]);
+    /// assert_eq!(remainder, &[5.]);
+    /// ```
+    pub fn as_chunks<const C: usize>(&self) -> (&[[A::Item; C]], &[A::Item]) {
+        assert!(C != 0, "chunk size must be non-zero");
+        let chunks_len = self.len() / C;
+        let (head, remainder) = self.split_at(chunks_len * C);
+        let chunks = unsafe {
+            slice::from_raw_parts(head.as_ptr() as *const [A::Item; C], chunks_len)
+        };
+        (chunks, remainder)
     }
 }
 
-impl<A: Array> AsMut<[A::Item]> for SmallVec<A> {
-    #[inline]
-    fn as_mut(&mut self) -> &mut [A::Item] {
-        self
+#[cfg(feature = "bytemuck")]
+impl<A: Array> SmallVec<A> where A::Item: Pod {
+    /// Returns the bytes of the elements, in memory order.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v: SmallVec<[u16; 4]> = SmallVec::from_slice(&[0x0102, 0x0304]);
+    /// assert_eq!(v.as_bytes().len(), 4);
+    /// ```
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(self.as_slice())
     }
-}
 
-impl<A: Array> Borrow<[A::Item]> for SmallVec<A> {
-    #[inline]
+    /// Copy bytes, such as those returned by [`as_bytes`](#method.as_bytes), into a new vector.
+    ///
+    /// The bytes need not be aligned for `A::Item`, but their length must be a multiple of its
+    /// size; otherwise this returns `PodCastError::OutputSliceWouldHaveSlop`. Zero-sized items
+    /// cannot be counted from bytes and return `PodCastError::SizeMismatch`.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+    /// let copy = SmallVec::<[u32; 4]>::from_bytes(v.as_bytes()).unwrap();
+    /// assert_eq!(copy, v);
+    /// assert!(SmallVec::<[u32; 4]>::from_bytes(&v.as_bytes()[1..]).is_err());
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PodCastError> {
+        let size = mem::size_of::<A::Item>();
+        if size == 0 {
+            return Err(PodCastError::SizeMismatch);
+        }
+        if bytes.len() % size != 0 {
+            return Err(PodCastError::OutputSliceWouldHaveSlop);
+        }
+        let len = bytes.len() / size;
+        let mut v = SmallVec::with_capacity(len);
+        unsafe {
+            let (ptr, len_ptr, _) = v.triple_mut();
+            ptr::copy_nonoverlapping(bytes.as_ptr(), ptr as *mut u8, bytes.len());
+            *len_ptr = len;
+        }
+        Ok(v)
+    }
+}
+
+impl<B: Array, A: Array<Item = SmallVec<B>>> SmallVec<A> {
+    /// Move the elements of all inner vectors, in order, to the end of `out`.
+    ///
+    /// The inner lengths are summed first so that `out` grows at most once, instead of
+    /// possibly once per inner vector as when extending from each in turn.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let groups: SmallVec<[SmallVec<[u8; 4]>; 2]> = SmallVec::from_vec(vec![
+    ///     SmallVec::from_slice(b"ab"),
+    ///     SmallVec::from_slice(b"cdefg"),
+    /// ]);
+    /// let mut out: SmallVec<[u8; 8]> = SmallVec::new();
+    /// groups.flatten_into(&mut out);
+    /// assert_eq!(&*out, b"abcdefg");
+    /// ```
+    pub fn flatten_into<C: Array<Item = B::Item>>(self, out: &mut SmallVec<C>) {
+        let total = self.iter().fold(0usize, |total, inner| {
+            total.checked_add(inner.len()).expect("capacity overflow")
+        });
+        out.reserve(total);
+        for mut inner in self {
+            unsafe {
+                let n = inner.len();
+                let (ptr, len_ptr, _) = out.triple_mut();
+                ptr::copy_nonoverlapping(inner.as_ptr(), ptr.offset(*len_ptr as isize), n);
+                *len_ptr += n;
+                // The elements now belong to `out`.
+                inner.set_len(0);
+            }
+        }
+    }
+}
+
+impl<A: Array<Item = u8>> SmallVec<A> {
+    /// Append the UTF-8 encoding of each `char` yielded by `iter`.
+    ///
+    /// Room for the lower bound of the iterator's size hint is reserved up front, one byte per
+    /// `char`; wider characters grow the buffer as needed.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 8]> = SmallVec::new();
+    /// v.extend_chars("caf\u{e9}".chars());
+    /// assert_eq!(&*v, "caf\u{e9}".as_bytes());
+    /// ```
+    pub fn extend_chars<I: IntoIterator<Item = char>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        let mut buf = [0; 4];
+        for c in iter {
+            self.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+
+    /// Converts the bytes to their ASCII upper case equivalents in place, like the slice method
+    /// of the same name. Non-ASCII bytes are unchanged.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 8]> = SmallVec::from_slice(b"Content-Type");
+    /// v.make_ascii_uppercase();
+    /// assert_eq!(&*v, b"CONTENT-TYPE");
+    /// ```
+    #[inline]
+    pub fn make_ascii_uppercase(&mut self) {
+        self.as_mut_slice().make_ascii_uppercase()
+    }
+
+    /// Converts the bytes to their ASCII lower case equivalents in place, like the slice method
+    /// of the same name. Non-ASCII bytes are unchanged.
+    #[inline]
+    pub fn make_ascii_lowercase(&mut self) {
+        self.as_mut_slice().make_ascii_lowercase()
+    }
+
+    /// Checks that the bytes equal `other`, ignoring ASCII case, like the slice method of the
+    /// same name.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v: SmallVec<[u8; 8]> = SmallVec::from_slice(b"Host");
+    /// assert!(v.eq_ignore_ascii_case(b"HOST"));
+    /// assert!(!v.eq_ignore_ascii_case(b"Hosts"));
+    /// ```
+    #[inline]
+    pub fn eq_ignore_ascii_case(&self, other: &[u8]) -> bool {
+        self.as_slice().eq_ignore_ascii_case(other)
+    }
+}
+
+impl<A: Array> SmallVec<A> where A::Item: Clone {
+    /// Resizes the vector so that its length is equal to `len`.
+    ///
+    /// If `len` is less than the current length, the vector simply truncated.
+    ///
+    /// If `len` is greater than the current length, `value` is appended to the
+    /// vector until its length equals `len`.
+    pub fn resize(&mut self, len: usize, value: A::Item) {
+        let old_len = self.len();
+
+        if len > old_len {
+            self.extend(repeat(value).take(len - old_len));
+        } else {
+            self.truncate(len);
+        }
+    }
+
+    /// Clone the elements of a slice into the vector at position `index`, shifting any
+    /// following elements toward the back.
+    ///
+    /// This is the `Clone` counterpart of [`insert_from_slice`](#method.insert_from_slice): it
+    /// reserves and moves the trailing elements only once. If a `clone` call panics, the
+    /// elements cloned so far stay in the vector, followed by the trailing elements.
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[String; 2]> = SmallVec::new();
+    /// v.push("a".to_owned());
+    /// v.push("d".to_owned());
+    /// v.insert_many_from_slice(1, &["b".to_owned(), "c".to_owned()]);
+    /// assert_eq!(&*v, &["a", "b", "c", "d"]);
+    /// ```
+    pub fn insert_many_from_slice(&mut self, index: usize, slice: &[A::Item]) {
+        // The tail has been moved `n` slots back; `written` slots of the gap are filled.
+        // Closes whatever is left of the gap and restores the length, also on unwind.
+        struct Guard<'a, T: 'a> {
+            ptr: *mut T,
+            len_ptr: &'a mut usize,
+            index: usize,
+            n: usize,
+            written: usize,
+            tail_len: usize,
+        }
+
+        impl<'a, T: 'a> Drop for Guard<'a, T> {
+            fn drop(&mut self) {
+                unsafe {
+                    let filled_end = self.ptr.offset((self.index + self.written) as isize);
+                    if self.written < self.n {
+                        let tail = self.ptr.offset((self.index + self.n) as isize);
+                        ptr::copy(tail, filled_end, self.tail_len);
+                    }
+                }
+                *self.len_ptr = self.index + self.written + self.tail_len;
+            }
+        }
+
+        let len = self.len();
+        assert!(index <= len);
+        self.reserve(slice.len());
+
+        unsafe {
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let n = slice.len();
+            ptr::copy(ptr.offset(index as isize), ptr.offset((index + n) as isize), len - index);
+            *len_ptr = index;
+            let mut guard = Guard { ptr, len_ptr, index, n, written: 0, tail_len: len - index };
+            for elem in slice {
+                ptr::write(ptr.offset((index + guard.written) as isize), elem.clone());
+                guard.written += 1;
+            }
+        }
+    }
+
+    /// Clone the elements of a slice onto the end of the vector, reserving once and writing the
+    /// clones straight into the buffer. For `Copy` items the loop compiles down to a copy of the
+    /// whole slice, as in [`extend_from_slice`](#method.extend_from_slice).
+    fn extend_from_slice_cloned(&mut self, slice: &[A::Item]) {
+        self.reserve(slice.len());
+        unsafe {
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let mut len = SetLenOnDrop::new(len_ptr);
+            let dst = ptr.offset(len.local_len as isize);
+            for (i, elem) in slice.iter().enumerate() {
+                ptr::write(dst.offset(i as isize), elem.clone());
+                len.increment_len(1);
+            }
+        }
+    }
+
+    /// Overwrites the contents of the vector with clones of the elements of `src`.
+    ///
+    /// Unlike the slice method of the same name, the lengths do not have to match: the vector
+    /// is truncated or extended to `src.len()`. The existing buffer is reused when it is large
+    /// enough, otherwise it is grown once.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+    /// v.clone_from_slice(&[4, 5]);
+    /// assert_eq!(&*v, &[4, 5]);
+    /// v.clone_from_slice(&[6, 7, 8, 9, 10]);
+    /// assert_eq!(&*v, &[6, 7, 8, 9, 10]);
+    /// ```
+    pub fn clone_from_slice(&mut self, src: &[A::Item]) {
+        self.truncate(src.len());
+        let (init, tail) = src.split_at(self.len());
+        self.as_mut_slice().clone_from_slice(init);
+        self.reserve(tail.len());
+        self.extend(tail.iter().cloned());
+    }
+
+    /// Clones the vector into a buffer with the same capacity as `self`.
+    ///
+    /// `clone` only allocates room for `self.len()` elements, so the clone of a spilled vector
+    /// with spare capacity may end up inline or tightly sized. This keeps the spare capacity,
+    /// and with it the spilled state, so the clone can be refilled without reallocating.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let mut v: SmallVec<[u8; 2]> = SmallVec::with_capacity(16);
+    /// v.push(1);
+    /// let c = v.clone_keep_capacity();
+    /// assert_eq!(&*c, &[1]);
+    /// assert_eq!(c.capacity(), 16);
+    /// assert!(c.spilled());
+    /// ```
+    pub fn clone_keep_capacity(&self) -> Self {
+        let mut new_vector = SmallVec::with_capacity(self.capacity());
+        new_vector.extend(self.iter().cloned());
+        new_vector
+    }
+
+    /// Creates a `SmallVec` with `n` copies of `elem`.
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v = SmallVec::<[char; 128]>::from_elem('d', 2);
+    /// assert_eq!(v, SmallVec::from_buf(['d', 'd']));
+    /// ```
+    pub fn from_elem(elem: A::Item, n: usize) -> Self {
+        if n > A::size() {
+            vec![elem; n].into()
+        } else {
+            let mut v = SmallVec::<A>::new();
+            unsafe {
+                let (ptr, len_ptr, _) = v.triple_mut();
+                let mut local_len = SetLenOnDrop::new(len_ptr);
+
+                for i in 0..n as isize {
+                    ::std::ptr::write(ptr.offset(i), elem.clone());
+                    local_len.increment_len(1);
+                }
+            }
+            v
+        }
+    }
+}
+
+impl<A: Array> ops::Deref for SmallVec<A> {
+    type Target = [A::Item];
+    #[inline]
+    fn deref(&self) -> &[A::Item] {
+        unsafe {
+            let (ptr, len, _) = self.triple();
+            slice::from_raw_parts(ptr, len)
+        }
+    }
+}
+
+impl<A: Array> ops::DerefMut for SmallVec<A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [A::Item] {
+        unsafe {
+            let (ptr, &mut len, _) = self.triple_mut();
+            slice::from_raw_parts_mut(ptr, len)
+        }
+    }
+}
+
+impl<A: Array> AsRef<[A::Item]> for SmallVec<A> {
+    #[inline]
+    fn as_ref(&self) -> &[A::Item] {
+        self
+    }
+}
+
+impl<A: Array> AsMut<[A::Item]> for SmallVec<A> {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [A::Item] {
+        self
+    }
+}
+
+impl<A: Array> AsRef<SmallVec<A>> for SmallVec<A> {
+    #[inline]
+    fn as_ref(&self) -> &SmallVec<A> {
+        self
+    }
+}
+
+impl<A: Array> AsMut<SmallVec<A>> for SmallVec<A> {
+    #[inline]
+    fn as_mut(&mut self) -> &mut SmallVec<A> {
+        self
+    }
+}
+
+impl<A: Array> Borrow<[A::Item]> for SmallVec<A> {
+    #[inline]
     fn borrow(&self) -> &[A::Item] {
         self
     }
@@ -1170,6 +3034,11 @@ impl<A: Array> BorrowMut<[A::Item]> for SmallVec<A> {
     }
 }
 
+/// Every write appends through [`SmallVec::extend_from_slice`], which grows the buffer to the
+/// next power of two of the required length when it is full. Many small writes therefore only
+/// reallocate a logarithmic number of times, whatever mix of write sizes is used.
+///
+/// [`SmallVec::extend_from_slice`]: struct.SmallVec.html#method.extend_from_slice
 #[cfg(feature = "std")]
 impl<A: Array<Item = u8>> io::Write for SmallVec<A> {
     #[inline]
@@ -1190,6 +3059,124 @@ impl<A: Array<Item = u8>> io::Write for SmallVec<A> {
     }
 }
 
+/// A byte `SmallVec` with a position, like `io::Cursor<Vec<u8>>`.
+///
+/// Writes overwrite the bytes at the position and extend the vector when they go past its end.
+/// Seeking past the end is allowed; a write there fills the gap with zeroes first.
+///
+/// ```
+/// use smallvec::{SmallVec, SmallVecCursor};
+/// use std::io::{Seek, SeekFrom, Write};
+///
+/// let mut c = SmallVecCursor::new(SmallVec::<[u8; 16]>::new());
+/// c.write_all(&[0, 0, b'h', b'i']).unwrap();
+/// c.seek(SeekFrom::Start(0)).unwrap();
+/// c.write_all(&[0, 2]).unwrap();
+/// assert_eq!(&*c.into_inner(), &[0, 2, b'h', b'i']);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct SmallVecCursor<A: Array<Item = u8>> {
+    inner: SmallVec<A>,
+    pos: u64,
+}
+
+#[cfg(feature = "std")]
+impl<A: Array<Item = u8>> SmallVecCursor<A> {
+    /// Creates a cursor over `inner`, positioned at its start.
+    pub fn new(inner: SmallVec<A>) -> Self {
+        SmallVecCursor { inner, pos: 0 }
+    }
+
+    /// Consumes the cursor, returning the underlying vector.
+    pub fn into_inner(self) -> SmallVec<A> {
+        self.inner
+    }
+
+    /// Returns a reference to the underlying vector.
+    pub fn get_ref(&self) -> &SmallVec<A> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying vector.
+    pub fn get_mut(&mut self) -> &mut SmallVec<A> {
+        &mut self.inner
+    }
+
+    /// Returns the current position.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Sets the position, which may be past the end of the vector.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A: Array<Item = u8>> io::Read for SmallVecCursor<A> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.len();
+        let start = cmp::min(self.pos, len as u64) as usize;
+        let n = cmp::min(buf.len(), len - start);
+        buf[..n].copy_from_slice(&self.inner[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A: Array<Item = u8>> io::Write for SmallVecCursor<A> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let pos = self.pos as usize;
+        if pos as u64 != self.pos || pos.checked_add(buf.len()).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "cursor position exceeds the maximum buffer length"));
+        }
+        if pos > self.inner.len() {
+            self.inner.resize(pos, 0);
+        }
+        let overlap = cmp::min(buf.len(), self.inner.len() - pos);
+        self.inner[pos..pos + overlap].copy_from_slice(&buf[..overlap]);
+        self.inner.extend_from_slice(&buf[overlap..]);
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A: Array<Item = u8>> io::Seek for SmallVecCursor<A> {
+    fn seek(&mut self, style: io::SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match style {
+            io::SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            io::SeekFrom::End(n) => (self.inner.len() as u64, n),
+            io::SeekFrom::Current(n) => (self.pos, n),
+        };
+        let pos = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.unsigned_abs())
+        };
+        match pos {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       "invalid seek to a negative or overflowing position")),
+        }
+    }
+}
+
 #[cfg(feature = "serde")]
 impl<A: Array> Serialize for SmallVec<A> where A::Item: Serialize {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
@@ -1230,11 +3217,34 @@ where A::Item: Deserialize<'de>,
         let len = seq.size_hint().unwrap_or(0);
         let mut values = SmallVec::with_capacity(len);
 
-        while let Some(value) = seq.next_element()? {
-            values.push(value);
+        // Go through `extend`, which writes straight into the spare capacity, and stash the
+        // first error to return once it stops.
+        let mut error = None;
+        values.extend(::std::iter::from_fn(|| match seq.next_element() {
+            Ok(value) => value,
+            Err(e) => {
+                error = Some(e);
+                None
+            }
+        }));
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(values),
         }
+    }
+}
+
+/// Iterate over mutable references to the elements in parallel, which also provides
+/// `par_iter_mut()`. This splits the slice of elements like `Vec` does, so it is only worth it
+/// for vectors long enough to have spilled.
+#[cfg(feature = "rayon")]
+impl<'a, A: Array> IntoParallelIterator for &'a mut SmallVec<A> where A::Item: Send {
+    type Iter = rayon::slice::IterMut<'a, A::Item>;
+    type Item = &'a mut A::Item;
 
-        Ok(values)
+    fn into_par_iter(self) -> Self::Iter {
+        self.as_mut_slice().par_iter_mut()
     }
 }
 
@@ -1274,21 +3284,71 @@ impl<'a, A: Array> From<&'a [A::Item]> for SmallVec<A> where A::Item: Clone {
     }
 }
 
-impl<A: Array> From<Vec<A::Item>> for SmallVec<A> {
+/// Copies the UTF-8 bytes of the string, so `let tag: SmallVec<[u8; 8]> = "GET".into();` works.
+impl<'a, A: Array<Item = u8>> From<&'a str> for SmallVec<A> {
     #[inline]
-    fn from(vec: Vec<A::Item>) -> SmallVec<A> {
-        SmallVec::from_vec(vec)
+    fn from(s: &'a str) -> SmallVec<A> {
+        SmallVec::from_slice(s.as_bytes())
     }
 }
 
-impl<A: Array> From<A> for SmallVec<A> {
+impl<'a, A: Array<Item = u8>> From<&'a String> for SmallVec<A> {
     #[inline]
-    fn from(array: A) -> SmallVec<A> {
-        SmallVec::from_buf(array)
+    fn from(s: &'a String) -> SmallVec<A> {
+        SmallVec::from_slice(s.as_bytes())
     }
 }
 
-macro_rules! impl_index {
+impl<A: Array> From<Vec<A::Item>> for SmallVec<A> {
+    #[inline]
+    fn from(vec: Vec<A::Item>) -> SmallVec<A> {
+        SmallVec::from_vec(vec)
+    }
+}
+
+/// Converts through [`SmallVec::into_vec`], which does not reallocate if the vector has spilled.
+///
+/// [`SmallVec::into_vec`]: struct.SmallVec.html#method.into_vec
+impl<A: Array> From<SmallVec<A>> for Vec<A::Item> {
+    #[inline]
+    fn from(vec: SmallVec<A>) -> Vec<A::Item> {
+        vec.into_vec()
+    }
+}
+
+/// Converts through [`SmallVec::into_inner`], so it fails, returning the vector, unless it is
+/// inline and full.
+///
+/// A blanket impl for every `A: Array` is not allowed by the orphan rules, so this only covers
+/// plain arrays.
+///
+/// [`SmallVec::into_inner`]: struct.SmallVec.html#method.into_inner
+///
+/// ```
+/// use smallvec::SmallVec;
+/// use std::convert::TryInto;
+///
+/// let v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3, 4]);
+/// let array: [u8; 4] = v.try_into().unwrap();
+/// assert_eq!(array, [1, 2, 3, 4]);
+/// ```
+impl<T, const N: usize> TryFrom<SmallVec<[T; N]>> for [T; N] where [T; N]: Array<Item = T> {
+    type Error = SmallVec<[T; N]>;
+
+    #[inline]
+    fn try_from(vec: SmallVec<[T; N]>) -> Result<[T; N], SmallVec<[T; N]>> {
+        vec.into_inner()
+    }
+}
+
+impl<A: Array> From<A> for SmallVec<A> {
+    #[inline]
+    fn from(array: A) -> SmallVec<A> {
+        SmallVec::from_buf(array)
+    }
+}
+
+macro_rules! impl_index {
     ($index_type: ty, $output_type: ty) => {
         impl<A: Array> ops::Index<$index_type> for SmallVec<A> {
             type Output = $output_type;
@@ -1307,15 +3367,46 @@ macro_rules! impl_index {
     }
 }
 
-impl_index!(usize, A::Item);
+impl<A: Array> SmallVec<A> {
+    /// In debug builds, panic with the storage state of the vector if `index` is out of bounds.
+    #[inline]
+    fn debug_check_index(&self, index: usize) {
+        if cfg!(debug_assertions) {
+            let len = self.len();
+            if index >= len {
+                panic!("index out of bounds: the len is {} but the index is {} \
+                        (spilled: {}, capacity: {})",
+                       len, index, self.spilled(), self.capacity());
+            }
+        }
+    }
+}
+
+impl<A: Array> ops::Index<usize> for SmallVec<A> {
+    type Output = A::Item;
+    #[inline]
+    fn index(&self, index: usize) -> &A::Item {
+        self.debug_check_index(index);
+        &(&**self)[index]
+    }
+}
+
+impl<A: Array> ops::IndexMut<usize> for SmallVec<A> {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut A::Item {
+        self.debug_check_index(index);
+        &mut (&mut **self)[index]
+    }
+}
+
 impl_index!(ops::Range<usize>, [A::Item]);
 impl_index!(ops::RangeFrom<usize>, [A::Item]);
 impl_index!(ops::RangeTo<usize>, [A::Item]);
 impl_index!(ops::RangeFull, [A::Item]);
 
-impl<A: Array> ExtendFromSlice<A::Item> for SmallVec<A> where A::Item: Copy {
+impl<A: Array> ExtendFromSlice<A::Item> for SmallVec<A> where A::Item: Clone {
     fn extend_from_slice(&mut self, other: &[A::Item]) {
-        SmallVec::extend_from_slice(self, other)
+        self.extend_from_slice_cloned(other)
     }
 }
 
@@ -1329,42 +3420,122 @@ impl<A: Array> VecLike<A::Item> for SmallVec<A> {
 
 impl<A: Array> FromIterator<A::Item> for SmallVec<A> {
     fn from_iter<I: IntoIterator<Item=A::Item>>(iterable: I) -> SmallVec<A> {
-        let mut v = SmallVec::new();
-        v.extend(iterable);
+        let iter = iterable.into_iter();
+        // If the exact length is known, allocate for exactly that many elements up front
+        // rather than rounding up to the next power of two.
+        let mut v = match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => SmallVec::with_capacity(lower),
+            _ => SmallVec::new(),
+        };
+        v.extend(iter);
         v
     }
 }
 
-impl<A: Array> Extend<A::Item> for SmallVec<A> {
-    fn extend<I: IntoIterator<Item=A::Item>>(&mut self, iterable: I) {
-        let mut iter = iterable.into_iter();
-        let (lower_size_bound, _) = iter.size_hint();
-        self.reserve(lower_size_bound);
+impl<A: Array> SmallVec<A> {
+    fn extend_each<I: Iterator<Item=A::Item>>(&mut self, mut iter: I) {
+        // An exact size hint is trusted up front. Any other hint is only acted on once the
+        // spare capacity is full, so an overestimate neither spills an inline vector nor grows
+        // a spilled one that had room to spare.
+        let (lower, upper) = iter.size_hint();
+        if upper == Some(lower) {
+            self.reserve(lower);
+        }
 
-        unsafe {
-            let len = self.len();
-            let ptr = self.as_mut_ptr().offset(len as isize);
-            let mut count = 0;
-            while count < lower_size_bound {
-                if let Some(out) = iter.next() {
-                    ptr::write(ptr.offset(count as isize), out);
-                    count += 1;
-                } else {
-                    break;
+        loop {
+            unsafe {
+                let (ptr, len_ptr, cap) = self.triple_mut();
+                let mut len = SetLenOnDrop::new(len_ptr);
+                while len.local_len < cap {
+                    match iter.next() {
+                        Some(elem) => {
+                            ptr::write(ptr.offset(len.local_len as isize), elem);
+                            len.increment_len(1);
+                        }
+                        None => return,
+                    }
+                }
+            }
+
+            // Only grow once an element actually does not fit, then make room for it and for
+            // what the iterator still promises, growing at least geometrically.
+            match iter.next() {
+                Some(elem) => {
+                    self.reserve(iter.size_hint().0.saturating_add(1));
+                    self.push(elem);
                 }
+                None => return,
             }
-            self.set_len(len + count);
         }
+    }
+}
 
-        for elem in iter {
-            self.push(elem);
+impl<A: Array> Extend<A::Item> for SmallVec<A> {
+    #[cfg(not(feature = "specialization"))]
+    #[inline]
+    fn extend<I: IntoIterator<Item=A::Item>>(&mut self, iterable: I) {
+        self.extend_each(iterable.into_iter())
+    }
+
+    #[cfg(feature = "specialization")]
+    #[inline]
+    fn extend<I: IntoIterator<Item=A::Item>>(&mut self, iterable: I) {
+        self.spec_extend(iterable.into_iter())
+    }
+}
+
+#[cfg(feature = "specialization")]
+trait SpecExtend<A: Array, I> {
+    fn spec_extend(&mut self, iter: I);
+}
+
+#[cfg(feature = "specialization")]
+impl<A: Array, I: Iterator<Item=A::Item>> SpecExtend<A, I> for SmallVec<A> {
+    #[inline]
+    default fn spec_extend(&mut self, iter: I) {
+        self.extend_each(iter)
+    }
+}
+
+// A `TrustedLen` iterator reports its exact length, so the whole batch can be written without
+// checking for the end of the iterator or for spare capacity. For `Copy` items read from a slice
+// (`slice.iter().cloned()` or `.copied()`) this loop compiles down to a bulk copy.
+#[cfg(feature = "specialization")]
+impl<A: Array, I: TrustedLen<Item=A::Item>> SpecExtend<A, I> for SmallVec<A>
+    where A::Item: Copy
+{
+    fn spec_extend(&mut self, iter: I) {
+        let additional = match iter.size_hint() {
+            (_, Some(upper)) => upper,
+            // More than `usize::MAX` elements.
+            (_, None) => panic!("capacity overflow"),
+        };
+        self.reserve(additional);
+        unsafe {
+            let (ptr, len_ptr, _) = self.triple_mut();
+            let mut len = SetLenOnDrop::new(len_ptr);
+            let dst = ptr.offset(len.local_len as isize);
+            for (i, elem) in iter.enumerate() {
+                ptr::write(dst.offset(i as isize), elem);
+            }
+            len.increment_len(additional);
         }
     }
 }
 
+/// `{:?}` formats a `SmallVec` like a slice. The alternate form `{:#?}` additionally shows
+/// whether the vector has spilled and its capacity.
 impl<A: Array> fmt::Debug for SmallVec<A> where A::Item: fmt::Debug {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_list().entries(self.iter()).finish()
+        if f.alternate() {
+            f.debug_struct("SmallVec")
+                .field("elements", &self.as_slice())
+                .field("spilled", &self.spilled())
+                .field("capacity", &self.capacity())
+                .finish()
+        } else {
+            f.debug_list().entries(self.iter()).finish()
+        }
     }
 }
 
@@ -1379,12 +3550,13 @@ impl<A: Array> Default for SmallVec<A> {
 unsafe impl<#[may_dangle] A: Array> Drop for SmallVec<A> {
     fn drop(&mut self) {
         unsafe {
-            if self.spilled() {
-                let (ptr, len) = self.data.heap();
-                Vec::from_raw_parts(ptr, len, self.capacity);
+            let _buffer = if self.spilled() {
+                let (ptr, _, cap) = self.triple_mut();
+                Some(DeallocOnDrop { ptr, capacity: cap })
             } else {
-                ptr::drop_in_place(&mut self[..]);
-            }
+                None
+            };
+            ptr::drop_in_place(&mut self[..]);
         }
     }
 }
@@ -1393,18 +3565,40 @@ unsafe impl<#[may_dangle] A: Array> Drop for SmallVec<A> {
 impl<A: Array> Drop for SmallVec<A> {
     fn drop(&mut self) {
         unsafe {
-            if self.spilled() {
-                let (ptr, len) = self.data.heap();
-                Vec::from_raw_parts(ptr, len, self.capacity);
+            let _buffer = if self.spilled() {
+                let (ptr, _, cap) = self.triple_mut();
+                Some(DeallocOnDrop { ptr, capacity: cap })
             } else {
-                ptr::drop_in_place(&mut self[..]);
-            }
+                None
+            };
+            ptr::drop_in_place(&mut self[..]);
         }
     }
 }
 
-impl<A: Array> Clone for SmallVec<A> where A::Item: Clone {
-    fn clone(&self) -> SmallVec<A> {
+#[cfg(feature = "specialization")]
+trait SpecClone {
+    fn spec_clone(&self) -> Self;
+}
+
+#[cfg(feature = "specialization")]
+impl<A: Array> SpecClone for SmallVec<A> where A::Item: Clone {
+    #[inline]
+    default fn spec_clone(&self) -> SmallVec<A> {
+        self.clone_each()
+    }
+}
+
+#[cfg(feature = "specialization")]
+impl<A: Array> SpecClone for SmallVec<A> where A::Item: Copy {
+    #[inline]
+    fn spec_clone(&self) -> SmallVec<A> {
+        SmallVec::from_slice(self)
+    }
+}
+
+impl<A: Array> SmallVec<A> where A::Item: Clone {
+    fn clone_each(&self) -> SmallVec<A> {
         let mut new_vector = SmallVec::with_capacity(self.len());
         for element in self.iter() {
             new_vector.push((*element).clone())
@@ -1413,6 +3607,25 @@ impl<A: Array> Clone for SmallVec<A> where A::Item: Clone {
     }
 }
 
+impl<A: Array> Clone for SmallVec<A> where A::Item: Clone {
+    #[cfg(not(feature = "specialization"))]
+    #[inline]
+    fn clone(&self) -> SmallVec<A> {
+        self.clone_each()
+    }
+
+    #[cfg(feature = "specialization")]
+    #[inline]
+    fn clone(&self) -> SmallVec<A> {
+        self.spec_clone()
+    }
+}
+
+// Both comparison and hashing go through the slice impls, which already take the fast path
+// for byte elements: equality becomes a single `memcmp` and hashing a single `Hasher::write`
+// of the whole buffer. `SmallVec<[u8; N]>` keys therefore hash exactly like `[u8]` and `Vec<u8>`,
+// so a map keyed by them can be queried with a plain `&[u8]`. Since only the contents take part,
+// equal vectors compare and hash equal whether or not either of them has spilled.
 impl<A: Array, B: Array> PartialEq<SmallVec<B>> for SmallVec<A>
     where A::Item: PartialEq<B::Item> {
     #[inline]
@@ -1423,6 +3636,8 @@ impl<A: Array, B: Array> PartialEq<SmallVec<B>> for SmallVec<A>
 
 impl<A: Array> Eq for SmallVec<A> where A::Item: Eq {}
 
+// The slice impls pick their fast paths from the element type wherever they are called from,
+// so forwarding to them is enough for byte vectors to be compared with `memcmp`.
 impl<A: Array> PartialOrd for SmallVec<A> where A::Item: PartialOrd {
     #[inline]
     fn partial_cmp(&self, other: &SmallVec<A>) -> Option<cmp::Ordering> {
@@ -1437,13 +3652,18 @@ impl<A: Array> Ord for SmallVec<A> where A::Item: Ord {
     }
 }
 
+/// Hashes exactly like the slice of elements. Since `PartialEq` works across inline sizes, so
+/// does this: vectors that compare equal hash equal, whatever their inline size and whether or
+/// not they have spilled. Any future specialized hashing must keep that property.
 impl<A: Array> Hash for SmallVec<A> where A::Item: Hash {
+    #[inline]
     fn hash<H: Hasher>(&self, state: &mut H) {
         (**self).hash(state)
     }
 }
 
 unsafe impl<A: Array> Send for SmallVec<A> where A::Item: Send {}
+unsafe impl<A: Array> Sync for SmallVec<A> where A::Item: Sync {}
 
 /// An iterator that consumes a `SmallVec` and yields its items by value.
 ///
@@ -1474,7 +3694,7 @@ impl<A: Array> Iterator for IntoIter<A> {
             unsafe {
                 let current = self.current as isize;
                 self.current += 1;
-                Some(ptr::read(self.data.as_ptr().offset(current)))
+                Some(ptr::read(self.data.triple().0.offset(current)))
             }
         }
     }
@@ -1484,6 +3704,64 @@ impl<A: Array> Iterator for IntoIter<A> {
         let size = self.end - self.current;
         (size, Some(size))
     }
+
+    #[inline]
+    fn nth(&mut self, n: usize) -> Option<A::Item> {
+        let skip = cmp::min(n, self.end - self.current);
+        let start = self.current;
+        self.current += skip;
+        unsafe { self.drop_range(start, skip) };
+        self.next()
+    }
+
+    #[inline]
+    fn count(mut self) -> usize {
+        let (start, len) = (self.current, self.end - self.current);
+        self.current = self.end;
+        unsafe { self.drop_range(start, len) };
+        len
+    }
+}
+
+impl<A: Array> IntoIter<A> {
+    /// Returns a reference to the element that `next` would yield, without consuming it.
+    ///
+    /// ```
+    /// use smallvec::SmallVec;
+    ///
+    /// let v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+    /// let mut iter = v.into_iter();
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(iter.next(), Some(1));
+    /// assert_eq!(iter.peek_back(), Some(&3));
+    /// ```
+    #[inline]
+    pub fn peek(&self) -> Option<&A::Item> {
+        if self.current == self.end {
+            None
+        } else {
+            unsafe { Some(&*self.data.triple().0.offset(self.current as isize)) }
+        }
+    }
+
+    /// Returns a reference to the element that `next_back` would yield, without consuming it.
+    #[inline]
+    pub fn peek_back(&self) -> Option<&A::Item> {
+        if self.current == self.end {
+            None
+        } else {
+            unsafe { Some(&*self.data.triple().0.offset(self.end as isize - 1)) }
+        }
+    }
+
+    /// Drop `len` elements starting at `start`, which must already be outside of
+    /// `current..end` so that a panicking destructor can't cause a double drop.
+    #[inline]
+    unsafe fn drop_range(&mut self, start: usize, len: usize) {
+        // `data` has length zero, so the pointer must not come from its (empty) slice.
+        let ptr = self.data.triple_mut().0.offset(start as isize);
+        ptr::drop_in_place(slice::from_raw_parts_mut(ptr, len));
+    }
 }
 
 impl<A: Array> DoubleEndedIterator for IntoIter<A> {
@@ -1495,10 +3773,19 @@ impl<A: Array> DoubleEndedIterator for IntoIter<A> {
         else {
             unsafe {
                 self.end -= 1;
-                Some(ptr::read(self.data.as_ptr().offset(self.end as isize)))
+                Some(ptr::read(self.data.triple().0.offset(self.end as isize)))
             }
         }
     }
+
+    #[inline]
+    fn nth_back(&mut self, n: usize) -> Option<A::Item> {
+        let skip = cmp::min(n, self.end - self.current);
+        self.end -= skip;
+        let end = self.end;
+        unsafe { self.drop_range(end, skip) };
+        self.next_back()
+    }
 }
 
 impl<A: Array> ExactSizeIterator for IntoIter<A> { }
@@ -1537,11 +3824,18 @@ impl<'a, A: Array> IntoIterator for &'a mut SmallVec<A> {
 }
 
 /// Types that can be used as the backing store for a SmallVec
+///
+/// The items must be stored contiguously from the start of the value, as in an array: a
+/// `SmallVec` reaches its inline items through a cast pointer to the possibly uninitialized
+/// backing store.
 pub unsafe trait Array {
     /// The type of the array's elements.
     type Item;
+    /// The number of items the array can hold.
+    const SIZE: usize;
     /// Returns the number of items the array can hold.
-    fn size() -> usize;
+    #[inline]
+    fn size() -> usize { Self::SIZE }
     /// Returns a pointer to the first element of the array.
     fn ptr(&self) -> *const Self::Item;
     /// Returns a mutable pointer to the first element of the array.
@@ -1580,7 +3874,7 @@ macro_rules! impl_array(
         $(
             unsafe impl<T> Array for [T; $size] {
                 type Item = T;
-                fn size() -> usize { $size }
+                const SIZE: usize = $size;
                 fn ptr(&self) -> *const T { self.as_ptr() }
                 fn ptr_mut(&mut self) -> *mut T { self.as_mut_ptr() }
             }
@@ -1592,6 +3886,53 @@ impl_array!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 20, 24, 32
             0x40, 0x80, 0x100, 0x200, 0x400, 0x800, 0x1000, 0x2000, 0x4000, 0x8000,
             0x10000, 0x20000, 0x40000, 0x80000, 0x100000);
 
+/// Implements [`Array`] for a newtype wrapping a fixed-size array, so that it can be used as the
+/// backing store of a [`SmallVec`].
+///
+/// This lets a library give its inline storage a domain-specific name, or use an inline size
+/// that this crate does not implement `Array` for. The wrapped array must be the newtype's first
+/// (`.0`) field. Generic newtypes are supported by listing their type parameters after `impl`.
+///
+/// ```
+/// # #[macro_use] extern crate smallvec;
+/// # use smallvec::SmallVec;
+/// struct Backing([u32; 17]);
+/// smallvec_impl_array!(Backing, [u32; 17]);
+///
+/// struct Generic<T>([T; 17]);
+/// smallvec_impl_array!(impl<T> Generic<T>, [T; 17]);
+///
+/// # fn main() {
+/// let mut v: SmallVec<Backing> = SmallVec::new();
+/// v.extend(0..17);
+/// assert_eq!(v.inline_size(), 17);
+/// assert!(!v.spilled());
+///
+/// let w: SmallVec<Generic<char>> = SmallVec::from_elem('a', 3);
+/// assert_eq!(&*w, &['a', 'a', 'a']);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! smallvec_impl_array {
+    (impl<$($param:ident),*> $name:ty, [$item:ty; $size:expr]) => {
+        unsafe impl<$($param),*> $crate::Array for $name {
+            type Item = $item;
+            const SIZE: usize = $size;
+            #[inline]
+            fn ptr(&self) -> *const $item {
+                { let array: &[$item; $size] = &self.0; array }.as_ptr()
+            }
+            #[inline]
+            fn ptr_mut(&mut self) -> *mut $item {
+                { let array: &mut [$item; $size] = &mut self.0; array }.as_mut_ptr()
+            }
+        }
+    };
+    ($name:ty, [$item:ty; $size:expr]) => {
+        smallvec_impl_array!(impl<> $name, [$item; $size]);
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use SmallVec;
@@ -1611,6 +3952,88 @@ mod tests {
     #[cfg(not(feature = "std"))]
     use alloc::vec::Vec;
 
+    #[cfg(feature = "std")]
+    use std::alloc::{GlobalAlloc, Layout, System};
+    #[cfg(feature = "std")]
+    use std::cell::Cell;
+
+    #[cfg(feature = "std")]
+    thread_local! {
+        // How many more allocations `FailingAlloc` grants on the current thread.
+        static ALLOCS_LEFT: Cell<usize> = const { Cell::new(usize::max_value()) };
+        // An address watched by `frees`, and whether the buffer containing it has been freed.
+        static WATCHED: Cell<(usize, bool)> = const { Cell::new((0, false)) };
+    }
+
+    /// Forwards to `System`, but fails once `ALLOCS_LEFT` reaches zero on the current thread.
+    #[cfg(feature = "std")]
+    struct FailingAlloc;
+
+    #[cfg(feature = "std")]
+    unsafe impl GlobalAlloc for FailingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let granted = ALLOCS_LEFT.try_with(|left| {
+                let n = left.get();
+                left.set(n.saturating_sub(1));
+                n > 0
+            }).unwrap_or(true);
+            if granted { System.alloc(layout) } else { ::std::ptr::null_mut() }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let start = ptr as usize;
+            let _ = WATCHED.try_with(|watched| {
+                let (addr, _) = watched.get();
+                if start <= addr && addr < start + layout.size() {
+                    watched.set((addr, true));
+                }
+            });
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[global_allocator]
+    static ALLOCATOR: FailingAlloc = FailingAlloc;
+
+    /// Runs `f` and returns its result along with the number of allocations it made.
+    #[cfg(feature = "std")]
+    fn count_allocs<R, F: FnOnce() -> R>(f: F) -> (R, usize) {
+        const LIMIT: usize = usize::max_value() / 2;
+        ALLOCS_LEFT.with(|left| left.set(LIMIT));
+        let result = f();
+        let allocs = LIMIT - ALLOCS_LEFT.with(|left| left.replace(usize::max_value()));
+        (result, allocs)
+    }
+
+    /// Hashes `t` with the standard library's default hasher.
+    #[cfg(feature = "std")]
+    fn hash_of<T: ::std::hash::Hash + ?Sized>(t: &T) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Runs `f` and returns whether it freed the heap buffer that `ptr` points into.
+    #[cfg(feature = "std")]
+    fn frees<T, F: FnOnce()>(ptr: *const T, f: F) -> bool {
+        WATCHED.with(|watched| watched.set((ptr as usize, false)));
+        f();
+        WATCHED.with(|watched| watched.replace((0, false))).1
+    }
+
+    /// Runs `f` with the current thread limited to `allocs` successful allocations.
+    #[cfg(feature = "std")]
+    fn with_alloc_limit<R, F: FnOnce() -> R>(allocs: usize, f: F) -> R {
+        ALLOCS_LEFT.with(|left| left.set(allocs));
+        let result = f();
+        ALLOCS_LEFT.with(|left| left.set(usize::max_value()));
+        result
+    }
+
     #[test]
     pub fn test_zero() {
         let mut v = SmallVec::<[_; 0]>::new();
@@ -1620,6 +4043,67 @@ mod tests {
         assert_eq!(&*v, &[0]);
     }
 
+    #[test]
+    pub fn test_zero_inline_capacity() {
+        let mut v = SmallVec::<[u8; 0]>::with_capacity(0);
+        assert!(!v.spilled());
+        assert_eq!(v.len(), 0);
+        assert_eq!(v.capacity(), 0);
+
+        v.reserve(0);
+        v.reserve_exact(0);
+        v.extend(None);
+        v.shrink_to_fit();
+        assert!(!v.spilled());
+        assert_eq!(v.pop(), None);
+
+        v.insert(0, 1);
+        assert!(v.spilled());
+        assert!(v.capacity() >= 1);
+        v.push(2);
+        assert_eq!(&*v, &[1, 2]);
+
+        v.clear();
+        assert!(v.spilled());
+        v.shrink_to_fit();
+        assert!(!v.spilled());
+        assert_eq!(v.capacity(), 0);
+
+        v.extend_from_slice(&[3]);
+        assert!(v.spilled());
+        assert_eq!(&*v, &[3]);
+    }
+
+    #[test]
+    fn test_over_aligned_items() {
+        use std::mem;
+
+        #[repr(align(64))]
+        struct Aligned([u8; 64]);
+
+        assert!(mem::align_of::<SmallVec<[Aligned; 2]>>() >= 64);
+
+        let mut v: SmallVec<[Aligned; 2]> = SmallVec::new();
+        v.push(Aligned([1; 64]));
+        v.push(Aligned([2; 64]));
+        assert!(!v.spilled());
+        for item in &v {
+            assert_eq!(item as *const Aligned as usize % 64, 0);
+        }
+
+        v.push(Aligned([3; 64]));
+        assert!(v.spilled());
+        for item in &v {
+            assert_eq!(item as *const Aligned as usize % 64, 0);
+        }
+
+        v.truncate(1);
+        v.shrink_to_fit();
+        assert!(!v.spilled());
+        assert_eq!(&v[0] as *const Aligned as usize % 64, 0);
+        assert_eq!(v[0].0[0], 1);
+    }
+
     // We heap allocate all these strings so that double frees will show up under valgrind.
 
     #[test]
@@ -1699,29 +4183,247 @@ mod tests {
     }
 
     #[test]
-    fn drain() {
-        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
-        v.push(3);
-        assert_eq!(v.drain().collect::<Vec<_>>(), &[3]);
-
-        // spilling the vec
-        v.push(3);
-        v.push(4);
-        v.push(5);
-        assert_eq!(v.drain().collect::<Vec<_>>(), &[3, 4, 5]);
+    fn test_new_capacity() {
+        macro_rules! check {
+            ($size:expr) => {{
+                let v: SmallVec<[u8; $size]> = SmallVec::new();
+                assert_eq!(v.capacity(), $size);
+                let v: SmallVec<[u8; $size]> = SmallVec::default();
+                assert_eq!(v.capacity(), $size);
+                let v: SmallVec<[u8; $size]> = SmallVec::with_capacity(0);
+                assert_eq!(v.capacity(), $size);
+                assert!(!v.spilled());
+            }}
+        }
+        check!(0);
+        check!(1);
+        check!(2);
+        check!(8);
+        check!(0x100);
     }
 
     #[test]
-    fn drain_rev() {
+    fn drain() {
         let mut v: SmallVec<[u8; 2]> = SmallVec::new();
         v.push(3);
-        assert_eq!(v.drain().rev().collect::<Vec<_>>(), &[3]);
+        assert_eq!(v.drain(..).collect::<Vec<_>>(), &[3]);
 
         // spilling the vec
         v.push(3);
         v.push(4);
         v.push(5);
-        assert_eq!(v.drain().rev().collect::<Vec<_>>(), &[5, 4, 3]);
+        assert_eq!(v.drain(..).collect::<Vec<_>>(), &[3, 4, 5]);
+    }
+
+    #[test]
+    fn extend_from_drain() {
+        let mut source: SmallVec<[u32; 8]> = (0..1000).collect();
+        let mut v: SmallVec<[u32; 8]> = SmallVec::new();
+        {
+            let drain = source.drain(..);
+            assert_eq!(drain.size_hint(), (1000, Some(1000)));
+            v.extend(drain);
+        }
+        assert!(source.is_empty());
+        assert_eq!(v.len(), 1000);
+        assert_eq!(v.capacity(), 1024);
+        assert!(v.iter().cloned().eq(0..1000));
+    }
+
+    #[test]
+    fn drain_range() {
+        for &inline in &[false, true] {
+            let mut v: SmallVec<[Box<u32>; 16]> = if inline {
+                SmallVec::new()
+            } else {
+                SmallVec::with_capacity(32)
+            };
+            v.extend((0..10).map(Box::new));
+            assert_eq!(v.spilled(), !inline);
+            {
+                let mut d = v.drain(2..7);
+                assert_eq!(d.len(), 5);
+                assert_eq!(d.next().map(|x| *x), Some(2));
+                assert_eq!(d.len(), 4);
+                assert_eq!(d.size_hint(), (4, Some(4)));
+                assert_eq!(d.next_back().map(|x| *x), Some(6));
+                assert_eq!(d.len(), 3);
+                assert_eq!(d.nth(1).map(|x| *x), Some(4));
+                assert_eq!(d.len(), 1);
+            }
+            assert_eq!(v.iter().map(|x| **x).collect::<Vec<_>>(), &[0, 1, 7, 8, 9]);
+
+            assert_eq!(v.drain(3..).map(|x| *x).collect::<Vec<_>>(), &[8, 9]);
+            assert_eq!(v.drain(..=0).map(|x| *x).collect::<Vec<_>>(), &[0]);
+            assert_eq!(v.drain(1..1).len(), 0);
+            assert_eq!(v.iter().map(|x| **x).collect::<Vec<_>>(), &[1, 7]);
+        }
+
+        // A leaked drain leaves the elements before the range in the vector.
+        let mut v: SmallVec<[u8; 2]> = SmallVec::from_slice(&[1, 2, 3, 4]);
+        ::std::mem::forget(v.drain(1..3));
+        assert_eq!(&*v, &[1]);
+    }
+
+    #[test]
+    fn test_forget_drain() {
+        use std::rc::Rc;
+
+        // Forgetting a drain leaks the drained elements and the tail, but must leave a vector
+        // that can be used and dropped without touching them again.
+        for &n in &[2, 5] {
+            let item = Rc::new(());
+            let mut v: SmallVec<[Rc<()>; 4]> = (0..n).map(|_| item.clone()).collect();
+            assert_eq!(v.spilled(), n > 4);
+            ::std::mem::forget(v.drain(..));
+            assert!(v.is_empty());
+            v.push(item.clone());
+            drop(v);
+            assert_eq!(Rc::strong_count(&item), 1 + n);
+
+            let item = Rc::new(());
+            let mut v: SmallVec<[Rc<()>; 4]> = (0..n).map(|_| item.clone()).collect();
+            ::std::mem::forget(v.drain(1..));
+            assert_eq!(v.len(), 1);
+            drop(v);
+            assert_eq!(Rc::strong_count(&item), n);
+
+            let item = Rc::new(());
+            let mut v: SmallVec<[Rc<()>; 4]> = (0..n).map(|_| item.clone()).collect();
+            ::std::mem::forget(v.drain_and_reset());
+            assert!(v.is_empty());
+            assert!(!v.spilled());
+            v.push(item.clone());
+            drop(v);
+            assert_eq!(Rc::strong_count(&item), 1 + n);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn drain_range_out_of_bounds() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+        v.drain(2..4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn drain_range_inverted() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+        v.drain(2..1);
+    }
+
+    #[test]
+    fn drain_rev() {
+        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
+        v.push(3);
+        assert_eq!(v.drain(..).rev().collect::<Vec<_>>(), &[3]);
+
+        // spilling the vec
+        v.push(3);
+        v.push(4);
+        v.push(5);
+        assert_eq!(v.drain(..).rev().collect::<Vec<_>>(), &[5, 4, 3]);
+    }
+
+    #[test]
+    fn drain_rev_spilled() {
+        let rc = Rc::new(());
+        let mut v: SmallVec<[Rc<()>; 2]> = (0..5).map(|_| rc.clone()).collect();
+        assert_eq!(v.drain(..).rev().count(), 5);
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert!(v.is_empty());
+        assert!(v.spilled());
+
+        v.extend((0..5).map(|_| rc.clone()));
+        {
+            let mut d = v.drain_and_reset().rev();
+            assert!(d.next().is_some());
+            assert_eq!(Rc::strong_count(&rc), 5);
+            assert_eq!(d.count(), 4);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert!(v.is_empty());
+        assert!(!v.spilled());
+        assert_eq!(v.capacity(), 2);
+    }
+
+    #[test]
+    fn drain_range_both_ends() {
+        // Interleave `next`, `next_back`, `nth` and `nth_back` on range drains of inline and
+        // spilled vectors, dropping the drain part way through, and check that every element
+        // is dropped exactly once and that the tail is moved back intact.
+        let mut seed = 12345u32;
+        let mut rand = move |n: usize| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as usize % n
+        };
+        for round in 0..500 {
+            let item = Rc::new(());
+            let len = rand(12);
+            let start = rand(len + 1);
+            let end = start + rand(len - start + 1);
+            let mut v: SmallVec<[(usize, Rc<()>); 4]> =
+                (0..len).map(|i| (i, item.clone())).collect();
+
+            let mut front = start;
+            let mut back = end;
+            {
+                let mut d = v.drain(start..end);
+                for _ in 0..rand(6) {
+                    // Skip `n` elements from one end, then take the next one from it.
+                    let n = rand(3);
+                    let from_back = rand(2) == 0;
+                    let got = match (from_back, n) {
+                        (false, 0) => d.next(),
+                        (false, _) => d.nth(n),
+                        (true, 0) => d.next_back(),
+                        (true, _) => d.nth_back(n),
+                    };
+                    let expected = if from_back {
+                        back = ::std::cmp::max(back.saturating_sub(n), front);
+                        if front < back { back -= 1; Some(back) } else { None }
+                    } else {
+                        front = ::std::cmp::min(front + n, back);
+                        if front < back { front += 1; Some(front - 1) } else { None }
+                    };
+                    assert_eq!(got.map(|x| x.0), expected, "round {}", round);
+                    assert_eq!(d.len(), back - front);
+                }
+            }
+
+            let expected: Vec<usize> = (0..start).chain(end..len).collect();
+            assert_eq!(v.iter().map(|x| x.0).collect::<Vec<_>>(), expected, "round {}", round);
+            assert_eq!(Rc::strong_count(&item), 1 + expected.len());
+        }
+    }
+
+    #[test]
+    fn drain_and_reset() {
+        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
+        v.push(3);
+        assert_eq!(v.drain_and_reset().collect::<Vec<_>>(), &[3]);
+        assert!(v.is_empty());
+
+        // spilling the vec
+        v.push(3);
+        v.push(4);
+        v.push(5);
+        assert!(v.spilled());
+        assert_eq!(v.drain_and_reset().count(), 3);
+        assert!(v.is_empty());
+        assert!(!v.spilled());
+
+        // dropping a partially consumed drain
+        let one = Rc::new(1);
+        let mut v: SmallVec<[Rc<i32>; 1]> = SmallVec::new();
+        v.push(Rc::clone(&one));
+        v.push(Rc::clone(&one));
+        assert_eq!(v.drain_and_reset().next().map(|x| *x), Some(1));
+        assert_eq!(Rc::strong_count(&one), 1);
+        assert!(!v.spilled());
+        v.push(Rc::clone(&one));
+        assert_eq!(&*v, &[Rc::clone(&one)]);
     }
 
     #[test]
@@ -1752,6 +4454,41 @@ mod tests {
         assert_eq!(v.into_iter().rev().collect::<Vec<_>>(), &[5, 4, 3]);
     }
 
+    #[test]
+    fn into_iter_nth() {
+        let v: SmallVec<[u8; 2]> = SmallVec::from_slice(&[0, 1, 2, 3, 4, 5]);
+        let mut it = v.into_iter();
+        assert_eq!(it.nth(1), Some(1));
+        assert_eq!(it.nth_back(1), Some(4));
+        assert_eq!(it.len(), 2);
+        assert_eq!(it.next(), Some(2));
+        assert_eq!(it.nth(5), None);
+        assert_eq!(it.next_back(), None);
+
+        let v: SmallVec<[u8; 8]> = SmallVec::from_slice(&[0, 1, 2, 3]);
+        let mut it = v.into_iter();
+        assert_eq!(it.nth_back(3), Some(0));
+        assert_eq!(it.next(), None);
+
+        let v: SmallVec<[u8; 2]> = SmallVec::from_slice(&[0, 1, 2, 3]);
+        let mut it = v.into_iter();
+        it.next();
+        assert_eq!(it.count(), 3);
+    }
+
+    #[test]
+    fn into_iter_nth_drop() {
+        let one = Rc::new(1);
+        let v: SmallVec<[Rc<i32>; 2]> = (0..6).map(|_| Rc::clone(&one)).collect();
+        let mut it = v.into_iter();
+        assert!(it.nth(2).is_some());
+        assert_eq!(Rc::strong_count(&one), 4);
+        assert!(it.nth_back(1).is_some());
+        assert_eq!(Rc::strong_count(&one), 2);
+        assert_eq!(it.count(), 1);
+        assert_eq!(Rc::strong_count(&one), 1);
+    }
+
     #[test]
     fn into_iter_drop() {
         use std::cell::Cell;
@@ -1806,143 +4543,1708 @@ mod tests {
     }
 
     #[test]
-    fn test_capacity() {
-        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
-        v.reserve(1);
-        assert_eq!(v.capacity(), 2);
-        assert!(!v.spilled());
+    fn test_capacity() {
+        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
+        v.reserve(1);
+        assert_eq!(v.capacity(), 2);
+        assert!(!v.spilled());
+
+        v.reserve_exact(0x100);
+        assert!(v.capacity() >= 0x100);
+
+        v.push(0);
+        v.push(1);
+        v.push(2);
+        v.push(3);
+
+        v.shrink_to_fit();
+        assert!(v.capacity() < 0x100);
+    }
+
+    #[test]
+    fn test_split_at_spare_mut() {
+        use std::mem::MaybeUninit;
+
+        let mut v: SmallVec<[u8; 4]> = SmallVec::new();
+        v.push(1);
+        {
+            let (init, spare) = v.split_at_spare_mut();
+            assert_eq!(init, &[1]);
+            assert_eq!(spare.len(), 3);
+            spare[0] = MaybeUninit::new(init[0] + 1);
+        }
+        unsafe { v.set_len(2) };
+        assert_eq!(&*v, &[1, 2]);
+
+        v.reserve_exact(6);
+        assert!(v.spilled());
+        {
+            let (init, spare) = v.split_at_spare_mut();
+            assert_eq!(init, &[1, 2]);
+            assert_eq!(spare.len(), 6);
+            for (i, slot) in spare.iter_mut().enumerate() {
+                *slot = MaybeUninit::new(i as u8 + 3);
+            }
+        }
+        unsafe { v.set_len(8) };
+        assert_eq!(&*v, &[1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_get_disjoint_mut() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2, 3, 4]);
+        {
+            let [a, b, c] = v.get_disjoint_mut([3, 0, 1]).unwrap();
+            *a += 10;
+            *b += 20;
+            *c += 30;
+        }
+        assert_eq!(&*v, &[21, 32, 3, 14]);
+
+        assert!(v.get_disjoint_mut([0, 1, 0]).is_none());
+        assert!(v.get_disjoint_mut([2, 2]).is_none());
+        assert!(v.get_disjoint_mut([1, 4]).is_none());
+        assert!(v.get_disjoint_mut([usize::max_value()]).is_none());
+        assert_eq!(v.get_disjoint_mut([]).map(|refs: [&mut u32; 0]| refs.len()), Some(0));
+
+        let mut empty: SmallVec<[u32; 2]> = SmallVec::new();
+        assert!(empty.get_disjoint_mut([0]).is_none());
+    }
+
+    #[test]
+    fn test_heap_ptr() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2]);
+        assert_eq!(v.heap_ptr(), None);
+        v.push(3);
+        assert_eq!(v.heap_ptr(), Some(v.as_ptr()));
+        assert_eq!(v.as_slice(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_insert_many_from_slice() {
+        let mut v: SmallVec<[String; 4]> = ["a", "e", "f"].iter().map(|s| s.to_string()).collect();
+        let middle: Vec<String> = ["b", "c", "d"].iter().map(|s| s.to_string()).collect();
+        v.insert_many_from_slice(1, &middle);
+        assert!(v.spilled());
+        assert_eq!(&*v, &["a", "b", "c", "d", "e", "f"]);
+        v.insert_many_from_slice(6, &middle[..1]);
+        v.insert_many_from_slice(0, &[]);
+        assert_eq!(&*v, &["a", "b", "c", "d", "e", "f", "b"]);
+
+        // A panicking clone leaves the elements cloned so far followed by the tail.
+        struct PanicOnClone(Rc<()>, bool);
+        impl Clone for PanicOnClone {
+            fn clone(&self) -> Self {
+                if self.1 { panic!() }
+                PanicOnClone(self.0.clone(), false)
+            }
+        }
+
+        let rc = Rc::new(());
+        let mut v: SmallVec<[PanicOnClone; 2]> = SmallVec::new();
+        v.push(PanicOnClone(rc.clone(), false));
+        v.push(PanicOnClone(rc.clone(), false));
+        let src = [PanicOnClone(rc.clone(), false), PanicOnClone(rc.clone(), true)];
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            v.insert_many_from_slice(1, &src);
+        }));
+        assert!(result.is_err());
+        assert_eq!(v.len(), 3);
+        assert_eq!(Rc::strong_count(&rc), 6);
+        drop(v);
+        drop(src);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_clone_from_slice() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2, 3, 4]);
+        let ptr = v.as_ptr();
+
+        // Shrinking and equal lengths reuse the existing allocation.
+        v.clone_from_slice(&[5, 6, 7]);
+        assert_eq!(&*v, &[5, 6, 7]);
+        assert_eq!(v.as_ptr(), ptr);
+        v.clone_from_slice(&[8, 9, 10]);
+        assert_eq!(&*v, &[8, 9, 10]);
+        assert_eq!(v.as_ptr(), ptr);
+
+        // Growing within capacity still does not reallocate.
+        v.clone_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(&*v, &[1, 2, 3, 4]);
+        assert_eq!(v.as_ptr(), ptr);
+
+        v.clone_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(&*v, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(v.capacity(), 8);
+
+        let mut v: SmallVec<[String; 2]> = SmallVec::new();
+        v.clone_from_slice(&["a".to_owned()]);
+        assert_eq!(&*v, &["a".to_owned()]);
+        assert!(!v.spilled());
+    }
+
+    // These exercise every path that writes or reads past `len()` through a raw pointer, in
+    // both the inline and the spilled state, and are meant to stay clean under Miri.
+    #[test]
+    fn test_raw_pointer_writes_past_len() {
+        let mut v: SmallVec<[u32; 4]> = SmallVec::new();
+        for i in 0..10 {
+            v.push(i);
+            v.insert(0, i);
+        }
+        assert_eq!(v.len(), 20);
+
+        let mut v: SmallVec<[u32; 8]> = SmallVec::from_slice(&[0, 1]);
+        v.insert_many(1, 2..4);
+        // A hint that is too high, then one that is too low.
+        v.insert_many(1, MockHintIter { x: 4..6, hint: 5 });
+        v.insert_many(1, MockHintIter { x: 10..14, hint: 1 });
+        assert_eq!(&*v, &[0, 10, 11, 12, 13, 4, 5, 2, 3, 1]);
+
+        let mut v: SmallVec<[u32; 8]> = SmallVec::from_slice(&[0, 1]);
+        v.extend((2..8).filter(|x| x % 2 == 0));
+        v.insert_from_slice(1, &[7, 8]);
+        v.extend_from_slice(&[9, 9, 9, 9, 9]);
+        assert_eq!(&*v, &[0, 7, 8, 1, 2, 4, 6, 9, 9, 9, 9, 9]);
+
+        for &n in &[2, 6] {
+            let v: SmallVec<[String; 4]> = (0..n).map(|i| i.to_string()).collect();
+            let mut iter = v.into_iter();
+            assert_eq!(iter.next_back(), Some((n - 1).to_string()));
+            assert_eq!(iter.nth(0), Some("0".to_owned()));
+            assert_eq!(iter.count(), n - 2);
+        }
+    }
+
+    #[test]
+    fn test_extend_from_slice_iter() {
+        let src: Vec<u8> = (0..100).collect();
+        let mut v: SmallVec<[u8; 8]> = SmallVec::from_slice(&[200, 201]);
+        v.extend(src[..4].iter().cloned());
+        assert!(!v.spilled());
+        v.extend(src.iter().copied());
+        assert_eq!(v.len(), 106);
+        assert_eq!(&v[..6], &[200, 201, 0, 1, 2, 3]);
+        assert_eq!(&v[6..], &src[..]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_extend_overestimated_hint_stays_inline() {
+        let mut v: SmallVec<[u8; 8]> = SmallVec::from_slice(&[1, 2]);
+        let ((), allocs) = count_allocs(|| v.extend(MockHintIter { x: 3..7, hint: 10 }));
+        assert_eq!(allocs, 0);
+        assert!(!v.spilled());
+        assert_eq!(&*v, &[1, 2, 3, 4, 5, 6]);
+
+        // Filling the inline buffer exactly does not spill either.
+        let ((), allocs) = count_allocs(|| v.extend(MockHintIter { x: 7..9, hint: 5 }));
+        assert_eq!(allocs, 0);
+        assert_eq!(&*v, &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // An honest hint past the inline buffer spills once, to its full size.
+        let mut v: SmallVec<[u8; 8]> = SmallVec::from_slice(&[1, 2]);
+        let ((), allocs) = count_allocs(|| v.extend(MockHintIter { x: 3..21, hint: 18 }));
+        assert_eq!(allocs, 1);
+        assert_eq!(v.capacity(), 32);
+        assert!(v.iter().cloned().eq(1..21));
+    }
+
+    #[test]
+    fn test_extend_unbounded() {
+        let mut n = 0;
+        let mut v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[100]);
+        v.extend(std::iter::from_fn(|| {
+            n += 1;
+            if n <= 40 { Some(n) } else { None }
+        }));
+        assert_eq!(v.len(), 41);
+        assert_eq!(v.capacity(), 64);
+        assert_eq!(v[0], 100);
+        assert!(v[1..].iter().cloned().eq(1..41));
+
+        // Elements written so far are kept if the iterator panics mid-chunk.
+        let rc = Rc::new(());
+        let mut v: SmallVec<[Rc<()>; 2]> = SmallVec::new();
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            let mut n = 0;
+            v.extend(std::iter::from_fn(|| {
+                n += 1;
+                if n == 6 { panic!() }
+                Some(rc.clone())
+            }));
+        }));
+        assert!(result.is_err());
+        assert_eq!(v.len(), 5);
+        drop(v);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_from_buf_and_len_dropping() {
+        let rc = Rc::new(());
+        let buf = [rc.clone(), rc.clone(), rc.clone(), rc.clone()];
+        let v: SmallVec<[Rc<()>; 4]> = SmallVec::from_buf_and_len_dropping(buf, 1);
+        assert_eq!(v.len(), 1);
+        assert_eq!(Rc::strong_count(&rc), 2);
+        drop(v);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_collect() {
+        use CollectionAllocErr;
+
+        let v = SmallVec::<[u32; 4]>::try_collect((0..10).filter(|_| true)).unwrap();
+        assert!(v.iter().cloned().eq(0..10));
+
+        // Inline, then 8, then 16; growing to 32 fails and the partial vector is dropped.
+        let rc = Rc::new(());
+        let result = with_alloc_limit(2, || {
+            SmallVec::<[Rc<()>; 4]>::try_collect((0..100).filter(|_| true).map(|_| rc.clone()))
+        });
+        #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
+        let layout = Layout::array::<Rc<()>>(32).unwrap();
+        #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+        let layout = ::header_layout::<Rc<()>>(32).unwrap().0;
+        assert_eq!(result.unwrap_err(), CollectionAllocErr::AllocErr { layout });
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        let result = SmallVec::<[u32; 4]>::try_collect(MockHintIter { x: 0..1, hint: usize::max_value() });
+        assert_eq!(result.unwrap_err(), CollectionAllocErr::CapacityOverflow);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_reserve() {
+        use CollectionAllocErr;
+
+        let mut v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2, 3]);
+        let ptr = v.as_ptr();
+        assert!(with_alloc_limit(0, || v.try_reserve(10)).is_err());
+        assert!(with_alloc_limit(0, || v.try_reserve_exact(10)).is_err());
+        assert_eq!(&*v, &[1, 2, 3]);
+        assert_eq!(v.capacity(), 3);
+        assert_eq!(v.as_ptr(), ptr);
+
+        assert_eq!(v.try_reserve(usize::max_value()), Err(CollectionAllocErr::CapacityOverflow));
+        assert_eq!(v.try_reserve_exact(usize::max_value()),
+                   Err(CollectionAllocErr::CapacityOverflow));
+        assert_eq!(v.try_reserve(10), Ok(()));
+        assert_eq!(v.capacity(), 16);
+        assert_eq!(v.try_reserve_exact(20), Ok(()));
+        assert_eq!(v.capacity(), 23);
+        assert_eq!(&*v, &[1, 2, 3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_shrink_to_fit() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::with_capacity(16);
+        v.extend(0..5);
+        let ptr = v.as_ptr();
+        assert!(with_alloc_limit(0, || v.try_shrink_to_fit()).is_err());
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 16);
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(&*v, &[0, 1, 2, 3, 4]);
+
+        assert_eq!(v.try_shrink_to_fit(), Ok(()));
+        assert_eq!(v.capacity(), 5);
+        assert_eq!(&*v, &[0, 1, 2, 3, 4]);
+
+        // Moving back inline does not allocate.
+        v.truncate(2);
+        assert_eq!(with_alloc_limit(0, || v.try_shrink_to_fit()), Ok(()));
+        assert!(!v.spilled());
+        assert_eq!(&*v, &[0, 1]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_shrink_to() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::with_capacity(16);
+        v.extend(0..5);
+        assert!(with_alloc_limit(0, || v.try_shrink_to(8)).is_err());
+        assert_eq!(v.capacity(), 16);
+        assert_eq!(&*v, &[0, 1, 2, 3, 4]);
+
+        assert_eq!(v.try_shrink_to(8), Ok(()));
+        assert_eq!(v.capacity(), 8);
+
+        // Moving back inline does not allocate.
+        v.truncate(1);
+        assert_eq!(with_alloc_limit(0, || v.try_shrink_to(0)), Ok(()));
+        assert!(!v.spilled());
+        assert_eq!(&*v, &[0]);
+    }
+
+    #[test]
+    fn test_shrink_to() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::with_capacity(16);
+        v.extend(0..5);
+        v.shrink_to(32);
+        assert_eq!(v.capacity(), 16);
+        v.shrink_to(10);
+        assert_eq!(v.capacity(), 10);
+        v.shrink_to(1);
+        assert_eq!(v.capacity(), 5);
+        assert_eq!(&*v, &[0, 1, 2, 3, 4]);
+
+        v.truncate(2);
+        v.shrink_to(1);
+        assert!(!v.spilled());
+        assert_eq!(&*v, &[0, 1]);
+        v.shrink_to(0);
+        assert_eq!(v.capacity(), 2);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_grow_to_current_capacity() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2, 3]);
+        let cap = v.capacity();
+        v.grow(cap);
+        assert_eq!(v.capacity(), cap);
+        assert_eq!(&*v, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_as_ptr_range() {
+        fn range_len<T>(range: ::std::ops::Range<*const T>) -> usize {
+            (range.end as usize - range.start as usize) / ::std::mem::size_of::<T>()
+        }
+
+        let mut v: SmallVec<[u32; 2]> = SmallVec::new();
+        for len in 0..4 {
+            assert_eq!(v.spilled(), len > 2);
+            let range = v.as_ptr_range();
+            assert_eq!(range.start, v.as_ptr());
+            assert_eq!(range_len(range), len);
+            let range = v.as_mut_ptr_range();
+            assert_eq!(range_len(range.start as *const _..range.end as *const _), len);
+            unsafe {
+                let mut p = range.start;
+                while p != range.end {
+                    *p += 1;
+                    p = p.offset(1);
+                }
+            }
+            v.push(0);
+        }
+        assert_eq!(&*v, &[3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_size_of() {
+        use std::mem::size_of;
+
+        const WORD: usize = size_of::<usize>();
+        // Inline buffer (or heap pointer and length) and capacity, plus the enum tag unless the
+        // `union` feature removes it. The compiler may fit the tag into padding. With the
+        // `capacity_header` feature, the heap variant is only the pointer.
+        let tag = if cfg!(feature = "union") { 0 } else { WORD };
+        let heap = if cfg!(all(feature = "capacity_header", not(feature = "union"))) {
+            WORD
+        } else {
+            2 * WORD
+        };
+        macro_rules! check {
+            ($item:ty, $size:expr) => {{
+                let data = ::std::cmp::max(size_of::<[$item; $size]>(), heap);
+                let data = (data + WORD - 1) / WORD * WORD;
+                let size = size_of::<SmallVec<[$item; $size]>>();
+                assert!(size >= data + WORD && size <= data + WORD + tag);
+            }}
+        }
+        check!(u8, 1);
+        check!(u8, 16);
+        check!(u32, 5);
+        check!(u64, 8);
+        check!(u8, 0x10000);
+    }
+
+    #[cfg(all(feature = "capacity_header", not(feature = "union")))]
+    #[test]
+    fn test_capacity_header() {
+        use std::mem::size_of;
+
+        // Length, enum tag and a one-word buffer: the heap pointer fits in that buffer now that
+        // the capacity is stored in the allocation.
+        const WORD: usize = size_of::<usize>();
+        assert_eq!(size_of::<SmallVec<[usize; 1]>>(), 3 * WORD);
+        assert_eq!(size_of::<SmallVec<[u8; 1]>>(), 3 * WORD);
+
+        let mut v: SmallVec<[u32; 1]> = SmallVec::new();
+        for i in 0..100 {
+            v.push(i);
+            assert_eq!(v.len(), i as usize + 1);
+            assert!(v.capacity() >= v.len());
+        }
+        assert!(v.iter().cloned().eq(0..100));
+        assert_eq!(v.capacity(), 128);
+        v.truncate(3);
+        v.shrink_to_fit();
+        assert_eq!(v.capacity(), 3);
+        v.truncate(1);
+        v.shrink_to_fit();
+        assert!(!v.spilled());
+
+        // Elements aligned more strictly than the header start further into the allocation.
+        #[repr(align(64))]
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Aligned(u8);
+        let v: SmallVec<[Aligned; 1]> = (0..5).map(Aligned).collect();
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+        assert_eq!(v.capacity(), 5);
+        assert_eq!(v[4], Aligned(4));
+
+        // Zero-sized items still get a header to hold the capacity.
+        let mut v: SmallVec<[(); 2]> = SmallVec::with_capacity(10);
+        v.extend((0..10).map(|_| ()));
+        assert_eq!(v.capacity(), 10);
+        assert_eq!(v.drain(..).count(), 10);
+
+        let v: SmallVec<[String; 1]> = SmallVec::from_vec(vec!["a".into(), "b".into()]);
+        assert_eq!(v.capacity(), 2);
+        assert_eq!(v.into_vec(), &["a", "b"]);
+    }
+
+    #[test]
+    fn test_split_at_mut() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2, 3, 4, 5]);
+        assert!(v.spilled());
+        {
+            let (left, right) = v.split_at_mut(2);
+            assert_eq!(left, &[1, 2]);
+            assert_eq!(right, &[3, 4, 5]);
+            left[0] = 10;
+            right[2] = 50;
+            left.swap(0, 1);
+        }
+        assert_eq!(&*v, &[2, 10, 3, 4, 50]);
+
+        let mut v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2]);
+        {
+            let (left, right) = v.split_at_mut(2);
+            assert_eq!(left, &[1, 2]);
+            assert!(right.is_empty());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_split_at_mut_out_of_bounds() {
+        let mut v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2]);
+        v.split_at_mut(3);
+    }
+
+    #[test]
+    fn test_push_after_reserve_exact() {
+        // Pushes up to the exactly reserved capacity keep it; the next one rounds up.
+        let mut v: SmallVec<[u32; 4]> = SmallVec::new();
+        v.reserve_exact(10);
+        assert_eq!(v.capacity(), 10);
+        for x in 0..10 {
+            v.push(x);
+            assert_eq!(v.capacity(), 10);
+        }
+        v.push(10);
+        assert_eq!(v.capacity(), 16);
+
+        // An exact reservation that fits inline does not spill.
+        let mut v: SmallVec<[u32; 4]> = SmallVec::new();
+        v.reserve_exact(3);
+        assert!(!v.spilled());
+        assert_eq!(v.capacity(), 4);
+    }
+
+    #[test]
+    fn test_reserve_after_reserve_exact() {
+        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
+        v.reserve_exact(5);
+        assert_eq!(v.capacity(), 5);
+        v.extend(0..5);
+        assert_eq!(v.capacity(), 5);
+        v.push(5);
+        assert_eq!(v.capacity(), 8);
+
+        v.reserve_exact(9);
+        assert_eq!(v.capacity(), 15);
+        v.reserve(9);
+        assert_eq!(v.capacity(), 15);
+        v.reserve(10);
+        assert_eq!(v.capacity(), 16);
+
+        // Repeated small reserves only grow when the vector is full.
+        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
+        let mut caps = Vec::new();
+        for x in 0..20 {
+            v.reserve(1);
+            v.push(x);
+            if caps.last() != Some(&v.capacity()) {
+                caps.push(v.capacity());
+            }
+        }
+        assert_eq!(caps, &[2, 4, 8, 16, 32]);
+    }
+
+    #[test]
+    fn test_clear_copy() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3, 4, 5]);
+        v.truncate(7);
+        assert_eq!(v.len(), 5);
+        v.truncate(3);
+        assert_eq!(&*v, &[1, 2, 3]);
+        v.clear();
+        assert!(v.is_empty());
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 5);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut v: SmallVec<[Box<u8>; 8]> = SmallVec::new();
+
+        for x in 0..8 {
+            v.push(Box::new(x));
+        }
+        v.truncate(4);
+
+        assert_eq!(v.len(), 4);
+        assert!(!v.spilled());
+
+        assert_eq!(*v.swap_remove(1), 1);
+        assert_eq!(*v.remove(1), 3);
+        v.insert(1, Box::new(3));
+
+        assert_eq!(&v.iter().map(|v| **v).collect::<Vec<_>>(), &[0, 3, 2]);
+    }
+
+    #[test]
+    fn test_truncate_front_to_back() {
+        use std::cell::RefCell;
+
+        struct Recorder<'a>(&'a RefCell<Vec<u8>>, u8);
+
+        impl<'a> Drop for Recorder<'a> {
+            fn drop(&mut self) {
+                self.0.borrow_mut().push(self.1);
+            }
+        }
+
+        let order = RefCell::new(Vec::new());
+        let mut v: SmallVec<[Recorder; 2]> = SmallVec::new();
+        for i in 0..5 {
+            v.push(Recorder(&order, i));
+        }
+        v.truncate_front_to_back(7);
+        assert_eq!(v.len(), 5);
+        v.truncate_front_to_back(1);
+        assert_eq!(v.len(), 1);
+        assert_eq!(&*order.borrow(), &[1, 2, 3, 4]);
+        drop(v);
+        assert_eq!(&*order.borrow(), &[1, 2, 3, 4, 0]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_truncate_front_to_back_panic() {
+        use std::cell::Cell;
+        use std::panic::{self, AssertUnwindSafe};
+
+        struct DropCounter<'a>(&'a Cell<i32>, bool);
+
+        impl<'a> Drop for DropCounter<'a> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+                if self.1 {
+                    panic!("drop");
+                }
+            }
+        }
+
+        let cell = Cell::new(0);
+        let mut v: SmallVec<[DropCounter; 4]> = SmallVec::new();
+        v.push(DropCounter(&cell, false));
+        v.push(DropCounter(&cell, true));
+        v.push(DropCounter(&cell, false));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| v.truncate_front_to_back(1)));
+        assert!(result.is_err());
+        assert_eq!(v.len(), 1);
+        assert_eq!(cell.get(), 2);
+        drop(v);
+        assert_eq!(cell.get(), 3);
+    }
+
+    #[test]
+    fn test_insert_many() {
+        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
+        for x in 0..4 {
+            v.push(x);
+        }
+        assert_eq!(v.len(), 4);
+        v.insert_many(1, [5, 6].iter().cloned());
+        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_insert_front() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::new();
+        for x in 0..6 {
+            v.insert(0, x);
+        }
+        assert_eq!(&*v, &[5, 4, 3, 2, 1, 0]);
+        v.insert(6, 6);
+        v.insert(3, 7);
+        assert_eq!(&*v, &[5, 4, 3, 7, 2, 1, 0, 6]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_insert_out_of_bounds() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3, 4]);
+        v.insert(5, 0);
+    }
+
+    #[test]
+    fn test_insert_from_iter() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[0, 3]);
+        v.insert_from_iter(1, 1..3);
+        assert_eq!(&*v, &[0, 1, 2, 3]);
+    }
+
+    struct MockHintIter<T: Iterator>{x: T, hint: usize}
+    impl<T: Iterator> Iterator for MockHintIter<T> {
+        type Item = T::Item;
+        fn next(&mut self) -> Option<Self::Item> {self.x.next()}
+        fn size_hint(&self) -> (usize, Option<usize>) {(self.hint, None)}
+    }
+
+    #[test]
+    fn test_insert_many_short_hint() {
+        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
+        for x in 0..4 {
+            v.push(x);
+        }
+        assert_eq!(v.len(), 4);
+        v.insert_many(1, MockHintIter{x: [5, 6].iter().cloned(), hint: 5});
+        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_insert_many_long_hint() {
+        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
+        for x in 0..4 {
+            v.push(x);
+        }
+        assert_eq!(v.len(), 4);
+        v.insert_many(1, MockHintIter{x: [5, 6].iter().cloned(), hint: 1});
+        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_insert_many_zero_hint() {
+        // The whole input is surplus to a lower bound of zero, and must grow the vector.
+        let mut v: SmallVec<[u64; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+        v.insert_many(0, (10..20).filter(|_| true));
+        assert!(v.iter().cloned().eq((10..20).chain(1..4)));
+
+        let mut v: SmallVec<[u64; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+        v.insert_many(2, MockHintIter{x: 10..13, hint: 1});
+        assert_eq!(&*v, &[1, 2, 10, 11, 12, 3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_insert_many_exact_size() {
+        let mut v: SmallVec<[u32; 4]> = (0..500).collect();
+        let items: Vec<u32> = (500..1000).collect();
+        let ((), allocs) = count_allocs(|| v.insert_many(0, items.into_iter()));
+        assert_eq!(allocs, 1);
+        assert!(v.iter().cloned().eq((500..1000).chain(0..500)));
+
+        // Exactly the hinted number of elements, but no room left over.
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[0, 3]);
+        v.insert_many(1, vec![1, 2]);
+        assert_eq!(&*v, &[0, 1, 2, 3]);
+        assert!(!v.spilled());
+    }
+
+    #[test]
+    fn test_extend_count() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::new();
+        assert_eq!(v.extend_count(None), 0);
+        assert_eq!(v.extend_count(0..3), 3);
+        assert!(!v.spilled());
+        assert_eq!(v.extend_count((3..10).filter(|x| x % 2 == 0)), 3);
+        assert!(v.spilled());
+        assert_eq!(&*v, &[0, 1, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn test_extend_long_hint() {
+        // A lying lower bound must not leave the vector spilled.
+        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
+        v.extend(MockHintIter{x: [1, 2].iter().cloned(), hint: 1000});
+        assert_eq!(&*v, &[1, 2]);
+        assert!(!v.spilled());
+        assert_eq!(v.capacity(), 8);
+
+        // Capacity reserved before the call is kept.
+        let mut v: SmallVec<[u8; 2]> = SmallVec::with_capacity(16);
+        v.extend(MockHintIter{x: [1, 2, 3].iter().cloned(), hint: 1000});
+        assert_eq!(&*v, &[1, 2, 3]);
+        assert_eq!(v.capacity(), 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn test_extend_max_hint() {
+        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
+        v.extend(MockHintIter{x: 0..3, hint: usize::max_value()});
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    // https://github.com/servo/rust-smallvec/issues/96
+    fn test_insert_many_panic() {
+        struct PanicOnDoubleDrop {
+            dropped: Box<bool>
+        }
+
+        impl Drop for PanicOnDoubleDrop {
+            fn drop(&mut self) {
+                assert!(!*self.dropped, "already dropped");
+                *self.dropped = true;
+            }
+        }
+
+        struct BadIter;
+        impl Iterator for BadIter {
+            type Item = PanicOnDoubleDrop;
+            fn size_hint(&self) -> (usize, Option<usize>) { (1, None) }
+            fn next(&mut self) -> Option<Self::Item> { panic!() }
+        }
+
+        let mut vec: SmallVec<[PanicOnDoubleDrop; 0]> = vec![
+            PanicOnDoubleDrop { dropped: Box::new(false) },
+            PanicOnDoubleDrop { dropped: Box::new(false) },
+        ].into();
+        let result = ::std::panic::catch_unwind(move || {
+            vec.insert_many(0, BadIter);
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    #[allow(deprecated)]
+    fn test_invalid_grow() {
+        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
+        v.extend(0..8);
+        v.grow(5);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "(spilled: true, capacity: 3)")]
+    fn test_index_out_of_bounds_spilled() {
+        let v: SmallVec<[u8; 2]> = SmallVec::from_slice(&[1, 2, 3]);
+        let _ = v[3];
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "length exceeds capacity")]
+    fn test_from_raw_parts_length_exceeds_capacity() {
+        let mut buf: Vec<u32> = Vec::with_capacity(4);
+        unsafe { SmallVec::<[u32; 2]>::from_raw_parts(buf.as_mut_ptr(), 5, 4); }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "misaligned pointer")]
+    fn test_from_raw_parts_misaligned() {
+        let mut buf: Vec<u32> = Vec::with_capacity(4);
+        let ptr = (buf.as_mut_ptr() as *mut u8).wrapping_offset(1) as *mut u32;
+        unsafe { SmallVec::<[u32; 2]>::from_raw_parts(ptr, 0, 3); }
+    }
+
+    #[test]
+    fn test_insert_from_slice() {
+        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
+        for x in 0..4 {
+            v.push(x);
+        }
+        assert_eq!(v.len(), 4);
+        v.insert_from_slice(1, &[5, 6]);
+        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_as_chunks() {
+        let v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+        let (chunks, remainder) = v.as_chunks::<2>();
+        assert_eq!(chunks, &[[1, 2]]);
+        assert_eq!(remainder, &[3]);
+
+        let v: SmallVec<[u32; 4]> = (0..11).collect();
+        assert!(v.spilled());
+        let (chunks, remainder) = v.as_chunks::<4>();
+        assert_eq!(chunks, &[[0, 1, 2, 3], [4, 5, 6, 7]]);
+        assert_eq!(remainder, &[8, 9, 10]);
+
+        let v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2]);
+        let (chunks, remainder) = v.as_chunks::<3>();
+        assert!(chunks.is_empty());
+        assert_eq!(remainder, &[1, 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_as_chunks_zero() {
+        let v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2]);
+        v.as_chunks::<0>();
+    }
+
+    #[test]
+    fn test_extend_from_slice() {
+        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
+        for x in 0..4 {
+            v.push(x);
+        }
+        assert_eq!(v.len(), 4);
+        v.extend_from_slice(&[5, 6]);
+        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 1, 2, 3, 5, 6]);
+    }
+
+    #[test]
+    fn test_extend_from_slice_trait_clone() {
+        use ExtendFromSlice;
+
+        fn fill<V: ExtendFromSlice<String>>(v: &mut V) {
+            let words = ["a".to_owned(), "b".to_owned(), "c".to_owned()];
+            v.extend_from_slice(&words);
+            v.extend_from_slice(&words[1..]);
+        }
+
+        let mut vec = Vec::new();
+        fill(&mut vec);
+        let mut small: SmallVec<[String; 2]> = SmallVec::new();
+        fill(&mut small);
+        assert_eq!(&*small, &vec[..]);
+        assert_eq!(&*small, &["a", "b", "c", "b", "c"]);
+    }
+
+    #[test]
+    fn test_extend_from_slice_mut() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2]);
+        {
+            let added = v.extend_from_slice_mut(&[3, 4]);
+            assert_eq!(added, &[3, 4]);
+            added[0] = 30;
+        }
+        assert!(!v.spilled());
+        let end = {
+            let added = v.extend_from_slice_mut(&[5, 6, 7]);
+            assert_eq!(added, &[5, 6, 7]);
+            added[2] = 70;
+            added.as_ptr_range().end
+        };
+        assert!(v.spilled());
+        assert_eq!(v.as_ptr_range().end, end);
+        assert_eq!(&*v, &[1, 2, 30, 4, 5, 6, 70]);
+        assert!(v.extend_from_slice_mut(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_from_str() {
+        let tag: SmallVec<[u8; 8]> = "GET".into();
+        assert_eq!(&*tag, b"GET");
+        assert!(!tag.spilled());
+
+        let long: SmallVec<[u8; 8]> = "Content-Length".into();
+        assert_eq!(&*long, b"Content-Length");
+        assert!(long.spilled());
+
+        let s = String::from("héllo");
+        let v = SmallVec::<[u8; 4]>::from(&s);
+        assert_eq!(&*v, s.as_bytes());
+        assert_eq!(v.len(), 6);
+    }
+
+    #[test]
+    fn test_to_smallvec() {
+        use ToSmallVec;
+
+        let bytes = [1u8, 2, 3];
+        let v = bytes.to_smallvec::<[u8; 4]>();
+        assert_eq!(&*v, &bytes);
+        assert!(!v.spilled());
+        let v: SmallVec<[u8; 2]> = bytes[..].to_smallvec();
+        assert_eq!(&*v, &bytes);
+        assert!(v.spilled());
+
+        let strings = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let v: SmallVec<[String; 2]> = strings.to_smallvec();
+        assert_eq!(&*v, &strings[..]);
+        assert!(v.spilled());
+    }
+
+    #[test]
+    fn test_extend_chars() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::new();
+        v.extend_chars(vec!['a', 'b']);
+        assert!(!v.spilled());
+        v.extend_chars(vec!['\u{e9}', '\u{1f600}']);
+        assert!(v.spilled());
+        assert_eq!(v.len(), 8);
+        assert_eq!(::std::str::from_utf8(&v), Ok("ab\u{e9}\u{1f600}"));
+    }
+
+    #[test]
+    fn test_array_windows() {
+        let v: SmallVec<[f32; 2]> = SmallVec::from_slice(&[1., 2., 3., 4., 5.]);
+        assert!(v.spilled());
+        let sums: Vec<f32> = v.array_windows::<3>().map(|w| w.iter().sum()).collect();
+        assert_eq!(sums, &[6., 9., 12.]);
+        assert_eq!(v.array_windows::<5>().count(), 1);
+        assert_eq!(v.array_windows::<6>().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "window size must be non-zero")]
+    fn test_array_windows_zero() {
+        let v: SmallVec<[u8; 2]> = SmallVec::from_slice(&[1, 2]);
+        let _ = v.array_windows::<0>();
+    }
+
+    #[test]
+    fn test_try_extend() {
+        let mut v: SmallVec<[String; 2]> = SmallVec::new();
+        let ok: Vec<Result<String, ()>> = vec![Ok("a".into()), Ok("b".into()), Ok("c".into())];
+        assert_eq!(v.try_extend(ok), Ok(()));
+        assert_eq!(&*v, &["a", "b", "c"]);
+
+        let mut v: SmallVec<[String; 2]> = SmallVec::from_vec(vec!["a".into()]);
+        let items = vec![Ok("b".into()), Ok("c".into()), Err(3), Ok("d".into())];
+        assert_eq!(v.try_extend(items), Err(3));
+        assert_eq!(&*v, &["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_as_ref_self() {
+        fn push_one<V: AsMut<SmallVec<[u8; 2]>>>(mut v: V) {
+            v.as_mut().push(1);
+        }
+        fn total<V: AsRef<SmallVec<[u8; 2]>>>(v: V) -> u8 {
+            v.as_ref().iter().sum()
+        }
+        fn bytes<V: AsRef<[u8]>>(v: V) -> usize {
+            v.as_ref().len()
+        }
+
+        let mut v: SmallVec<[u8; 2]> = SmallVec::from_slice(&[2]);
+        push_one(&mut v);
+        assert_eq!(total(&v), 3);
+        assert_eq!(total(v.clone()), 3);
+        assert_eq!(bytes(&v), 2);
+    }
+
+    #[test]
+    fn test_from_const_buf() {
+        const EMPTY: SmallVec<[u8; 4]> = SmallVec::from_const_buf([0; 4], 0);
+        static FULL: SmallVec<[&str; 2]> = SmallVec::from_const_buf(["a", "b"], 2);
+
+        assert!(EMPTY.is_empty());
+        assert_eq!(EMPTY.capacity(), 4);
+        assert_eq!(&*FULL, &["a", "b"]);
+        assert!(!FULL.spilled());
+
+        let mut v = EMPTY;
+        v.extend(0..5);
+        assert_eq!(&*v, &[0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_const_buf_too_long() {
+        SmallVec::from_const_buf([1u8, 2], 3);
+    }
+
+    #[test]
+    fn test_chunks_mut() {
+        let mut v: SmallVec<[u8; 16]> = (0..40).collect();
+        assert!(v.spilled());
+        {
+            let mut blocks = v.chunks_exact_mut(16);
+            for block in &mut blocks {
+                for b in block.iter_mut() {
+                    *b ^= 0xff;
+                }
+            }
+            assert_eq!(blocks.into_remainder().len(), 8);
+        }
+        assert_eq!(v[15], !15);
+        assert_eq!(v[31], !31);
+        assert_eq!(v[32], 32);
+
+        let lens: Vec<usize> = v.chunks_mut(16).map(|c| c.len()).collect();
+        assert_eq!(lens, &[16, 16, 8]);
+    }
+
+    #[cfg(any(not(feature = "capacity_header"), feature = "union"))]
+    #[test]
+    fn test_vec_round_trip_keeps_allocation() {
+        let mut v: SmallVec<[u32; 4]> = SmallVec::with_capacity(8);
+        v.extend(0..2);
+        let ptr = v.as_ptr();
+
+        let vec = v.into_vec();
+        assert_eq!(vec.as_ptr(), ptr);
+        assert_eq!(vec.capacity(), 8);
+        let v = SmallVec::<[u32; 4]>::from_vec(vec);
+        assert!(v.spilled());
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(v.capacity(), 8);
+
+        let mut vec = Vec::from(v);
+        vec.push(2);
+        assert_eq!(vec.as_ptr(), ptr);
+        let v: SmallVec<[u32; 4]> = vec.into();
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(&*v, &[0, 1, 2]);
+
+        // Once the `Vec` no longer has more room than the inline buffer, the data moves back.
+        let mut vec = v.into_vec();
+        vec.shrink_to_fit();
+        let v = SmallVec::<[u32; 4]>::from_vec(vec);
+        assert!(!v.spilled());
+        assert_eq!(&*v, &[0, 1, 2]);
+    }
+
+    #[test]
+    fn test_unique() {
+        let mut v: SmallVec<[String; 4]> = ["b", "a", "b", "c", "a", "d", "c"].iter()
+            .map(|s| s.to_string())
+            .collect();
+        v.unique();
+        assert_eq!(&*v, &["b", "a", "c", "d"]);
+
+        // Long enough to take the hash set path.
+        let mut v: SmallVec<[u32; 8]> = (0..200).map(|i| (i * 7) % 50).collect();
+        assert!(v.spilled());
+        v.unique();
+        let expected: Vec<u32> = (0..50).map(|i| (i * 7) % 50).collect();
+        assert_eq!(&*v, &expected[..]);
+
+        let mut v: SmallVec<[u32; 2]> = SmallVec::new();
+        v.unique();
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_try_into_array() {
+        use std::convert::{TryFrom, TryInto};
+
+        let v: SmallVec<[String; 2]> = SmallVec::from_vec(vec!["a".into(), "b".into()]);
+        let array: [String; 2] = v.try_into().unwrap();
+        assert_eq!(array, ["a", "b"]);
+
+        let short: SmallVec<[String; 2]> = SmallVec::from_vec(vec!["a".into()]);
+        let err = <[String; 2]>::try_from(short).unwrap_err();
+        assert_eq!(&*err, &["a"]);
+
+        let long: SmallVec<[String; 2]> = SmallVec::from_vec(vec!["a".into(); 3]);
+        let err: Result<[String; 2], _> = long.try_into();
+        assert_eq!(err.unwrap_err().len(), 3);
+    }
+
+    #[test]
+    fn test_retain_changed() {
+        for &len in &[3, 10] {
+            let mut v: SmallVec<[u32; 4]> = (0..len).collect();
+            assert_eq!(v.spilled(), len > 4);
+            assert!(!v.retain_changed(|_| true));
+            assert_eq!(v.len(), len as usize);
+            assert!(v.retain_changed(|&x| x % 2 == 0));
+            assert!(v.iter().all(|&x| x % 2 == 0));
+            assert!(!v.retain_changed(|&x| x % 2 == 0));
+            assert!(v.retain_changed(|_| false));
+            assert!(v.is_empty());
+            assert!(!v.retain_changed(|_| false));
+        }
+    }
+
+    #[test]
+    fn test_replace_range_copy() {
+        let mut v: SmallVec<[u8; 4]> = (0..10).collect();
+        assert!(v.spilled());
+        let ptr = v.as_ptr();
+        v.replace_range_copy(3..6, &[30, 40, 50]);
+        assert_eq!(&*v, &[0, 1, 2, 30, 40, 50, 6, 7, 8, 9]);
+        v.replace_range_copy(8.., &[80, 90]);
+        v.replace_range_copy(..=0, &[100]);
+        v.replace_range_copy(5..5, &[]);
+        assert_eq!(&*v, &[100, 1, 2, 30, 40, 50, 6, 7, 80, 90]);
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(v.len(), 10);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_replace_range_copy_len_mismatch() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+        v.replace_range_copy(0..2, &[1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_replace_range_copy_out_of_bounds() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1, 2, 3]);
+        v.replace_range_copy(2..4, &[1, 2]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_from_iter_inline() {
+        let (v, allocs) = count_allocs(|| SmallVec::<[u32; 4]>::try_from_iter_inline(0..4));
+        let v = v.unwrap();
+        assert_eq!(&*v, &[0, 1, 2, 3]);
+        assert!(!v.spilled());
+        assert_eq!(allocs, 0);
+
+        let mut iter = 0..10;
+        let partial = SmallVec::<[u32; 4]>::try_from_iter_inline(&mut iter).unwrap_err();
+        assert_eq!(&*partial, &[0, 1, 2, 3, 4]);
+        assert_eq!(iter.next(), Some(5));
+
+        let empty = SmallVec::<[u32; 0]>::try_from_iter_inline(None).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_inline_capacity_const() {
+        const CAP: usize = SmallVec::<[u32; 5]>::INLINE_CAPACITY;
+        const _: () = assert!(CAP == 5);
+        let buf = [0u8; SmallVec::<[String; 3]>::INLINE_CAPACITY];
+        assert_eq!(buf.len(), 3);
+        assert_eq!(SmallVec::<[u32; 5]>::new().inline_size(), CAP);
+        assert_eq!(<[u32; 0] as ::Array>::SIZE, 0);
+    }
+
+    #[test]
+    fn test_drain_into_slice() {
+        let mut v: SmallVec<[u32; 4]> = (0..10).collect();
+        let cap = v.capacity();
+        let mut out = [0; 3];
+        assert_eq!(v.drain_into_slice(&mut out), 3);
+        assert_eq!(out, [0, 1, 2]);
+        assert_eq!(&*v, &[3, 4, 5, 6, 7, 8, 9]);
+        assert!(v.spilled());
+
+        let mut out = [0; 16];
+        assert_eq!(v.drain_into_slice(&mut out), 7);
+        assert_eq!(&out[..7], &[3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(&out[7..], &[0; 9]);
+        assert!(v.is_empty());
+        assert_eq!(v.capacity(), cap);
+
+        assert_eq!(v.drain_into_slice(&mut out), 0);
+        let mut v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2]);
+        assert_eq!(v.drain_into_slice(&mut []), 0);
+        assert_eq!(&*v, &[1, 2]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_flatten_into() {
+        let groups: SmallVec<[SmallVec<[String; 2]>; 4]> = (0..10)
+            .map(|i| (0..i % 4).map(|j| format!("{}.{}", i, j)).collect())
+            .collect();
+        let expected: Vec<String> = groups.iter().flat_map(|g| g.iter().cloned()).collect();
+
+        let mut out: SmallVec<[String; 4]> = SmallVec::from_vec(vec!["start".to_owned()]);
+        let ((), allocs) = count_allocs(|| groups.flatten_into(&mut out));
+        // One allocation for `out`; freeing the spilled inner vectors does not count.
+        assert_eq!(allocs, 1);
+        assert_eq!(out[0], "start");
+        assert_eq!(&out[1..], &expected[..]);
+    }
+
+    #[test]
+    fn test_ascii_case() {
+        let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(b"ab");
+        v.make_ascii_uppercase();
+        assert_eq!(&*v, b"AB");
+        v.extend_from_slice(b"cd\xe9f");
+        assert!(v.spilled());
+        v.make_ascii_uppercase();
+        assert_eq!(&*v, b"ABCD\xe9F");
+        assert!(v.eq_ignore_ascii_case(b"abcd\xe9f"));
+        assert!(!v.eq_ignore_ascii_case(b"abcd\xc9f"));
+        v.make_ascii_lowercase();
+        assert_eq!(&*v, b"abcd\xe9f");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_repeat_with() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::rc::Rc;
+
+        let v: SmallVec<[String; 2]> = SmallVec::from_repeat_with(0, || unreachable!());
+        assert!(v.is_empty());
+        let v: SmallVec<[String; 2]> = SmallVec::from_repeat_with(3, String::new);
+        assert_eq!(&*v, &["", "", ""]);
+
+        for &k in &[1, 4] {
+            let item = Rc::new(());
+            let mut calls = 0;
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                SmallVec::<[Rc<()>; 2]>::from_repeat_with(6, || {
+                    if calls == k {
+                        panic!("boom");
+                    }
+                    calls += 1;
+                    item.clone()
+                })
+            }));
+            assert!(result.is_err());
+            assert_eq!(calls, k);
+            assert_eq!(Rc::strong_count(&item), 1);
+        }
+    }
+
+    #[test]
+    fn test_reverse() {
+        for len in 0..8 {
+            let expected: Vec<u32> = (0..len).rev().collect();
+            let mut v: SmallVec<[u32; 4]> = (0..len).collect();
+            let (spilled, cap) = (v.spilled(), v.capacity());
+            v.reverse();
+            assert_eq!(&*v, &*expected);
+            assert_eq!(v.spilled(), spilled);
+            assert_eq!(v.capacity(), cap);
+        }
+
+        let mut v: SmallVec<[String; 2]> = SmallVec::new();
+        v.extend(["a", "b", "c"].iter().map(|s| s.to_string()));
+        v.reverse();
+        assert_eq!(&*v, &["c", "b", "a"]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_shrink_to_inline_size_boundary() {
+        const N: usize = 4;
+        let shrinks: [fn(&mut SmallVec<[u32; N]>); 2] = [
+            |v| v.shrink_to_fit(),
+            |v| v.shrink_to(N),
+        ];
+        for shrink in shrinks.iter() {
+            for k in 1..6 {
+                let mut v: SmallVec<[u32; N]> = (0..(N + k) as u32).collect();
+                assert!(v.spilled());
+                v.truncate(N);
+                assert!(v.spilled());
+                shrink(&mut v);
+                assert!(!v.spilled());
+                assert_eq!(v.capacity(), N);
+                assert_eq!(&*v, &[0, 1, 2, 3]);
+
+                // Refill up to exactly the inline size without touching the allocator...
+                v.truncate(1);
+                let ((), allocs) = count_allocs(|| v.extend(1..N as u32));
+                assert_eq!(allocs, 0);
+                assert!(!v.spilled());
+                assert_eq!(v.len(), N);
+
+                // ...and only the next push spills.
+                let ((), allocs) = count_allocs(|| v.push(N as u32));
+                assert_eq!(allocs, 1);
+                assert!(v.spilled());
+                assert_eq!(&*v, &[0, 1, 2, 3, 4]);
+            }
+        }
+
+        // Shrinking a short spilled vector with a minimum of exactly the inline size also lands
+        // back inline.
+        let mut v: SmallVec<[u32; N]> = SmallVec::with_capacity(N + 1);
+        v.push(7);
+        v.shrink_to(N);
+        assert!(!v.spilled());
+        assert_eq!(v.capacity(), N);
+
+        // Reserving exactly the inline size on an empty vector never spills.
+        let mut v: SmallVec<[u32; N]> = SmallVec::new();
+        let ((), allocs) = count_allocs(|| v.reserve_exact(N));
+        assert_eq!(allocs, 0);
+        assert!(!v.spilled());
+        assert_eq!(v.capacity(), N);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_extend_from_array_by_value() {
+        let mut v: SmallVec<[u8; 2]> = SmallVec::new();
+        let ((), allocs) = count_allocs(|| v.extend([1u8, 2, 3, 4, 5]));
+        assert_eq!(allocs, 1);
+        assert!(v.spilled());
+        assert_eq!(&*v, &[1, 2, 3, 4, 5]);
+
+        // The elements are moved in, not cloned through a reference.
+        let rc = Rc::new(());
+        let mut v: SmallVec<[Rc<()>; 2]> = SmallVec::new();
+        v.extend([rc.clone(), rc.clone(), rc.clone()]);
+        assert_eq!(Rc::strong_count(&rc), 4);
+        drop(v);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_iter_exact() {
+        let iter = (0..1000u32).filter(|x| x % 3 == 0);
+        assert_eq!(iter.size_hint(), (0, Some(1000)));
+        let (v, allocs) = count_allocs(|| SmallVec::<[u32; 4]>::from_iter_exact(iter));
+        assert_eq!(allocs, 1);
+        assert_eq!(v.len(), 334);
+        assert_eq!(v.capacity(), 334);
+        assert!(v.iter().cloned().eq((0..1000).step_by(3)));
+
+        let (v, allocs) = count_allocs(|| SmallVec::<[u32; 4]>::from_iter_exact(0..3));
+        assert_eq!(allocs, 0);
+        assert_eq!(&*v, &[0, 1, 2]);
+    }
+
+    #[test]
+    fn test_swap_remove_indexed() {
+        // Keep a reverse map from values to their positions up to date.
+        let mut v: SmallVec<[u32; 2]> = (0..6).collect();
+        let mut pos: Vec<usize> = (0..6).collect();
+        for &value in &[2, 5, 0, 3, 4, 1] {
+            let (removed, moved_from) = v.swap_remove_indexed(pos[value as usize]);
+            assert_eq!(removed, value);
+            match moved_from {
+                Some(from) => {
+                    assert_eq!(from, v.len());
+                    let index = pos[value as usize];
+                    pos[v[index] as usize] = index;
+                }
+                None => assert_eq!(pos[value as usize], v.len()),
+            }
+            for (i, &x) in v.iter().enumerate() {
+                assert_eq!(pos[x as usize], i);
+            }
+        }
+        assert!(v.is_empty());
+
+        let mut v: SmallVec<[String; 2]> = SmallVec::new();
+        v.push("only".into());
+        assert_eq!(v.swap_remove_indexed(0), ("only".to_string(), None));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_swap_remove_indexed_out_of_bounds() {
+        let mut v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2]);
+        v.swap_remove_indexed(2);
+    }
+
+    #[test]
+    fn test_zst_len_past_isize_max() {
+        // Zero-sized items take no memory, so any length is fine and every offset is 0.
+        let big = isize::max_value() as usize + 10;
+        let mut v: SmallVec<[(); 4]> = SmallVec::with_capacity(usize::max_value());
+        assert_eq!(v.capacity(), usize::max_value());
+        unsafe { v.set_len(big) };
+        v.push(());
+        v.insert(big - 3, ());
+        assert_eq!(v.len(), big + 2);
+        assert_eq!(v.pop(), Some(()));
+        assert_eq!(v.remove(big), ());
+        assert_eq!(v.swap_remove(7), ());
+        assert_eq!(v.len(), big - 1);
+        v.truncate(3);
+        assert_eq!(&*v, &[(), (), ()]);
+    }
 
-        v.reserve_exact(0x100);
-        assert!(v.capacity() >= 0x100);
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn test_reserve_past_isize_max_bytes() {
+        let mut v: SmallVec<[u16; 4]> = SmallVec::new();
+        v.reserve_exact(isize::max_value() as usize / 2 + 1);
+    }
 
-        v.push(0);
-        v.push(1);
-        v.push(2);
-        v.push(3);
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_iter_mut() {
+        use rayon::prelude::*;
 
-        v.shrink_to_fit();
-        assert!(v.capacity() < 0x100);
+        let mut v: SmallVec<[u32; 8]> = (0..100_000).collect();
+        assert!(v.spilled());
+        v.par_iter_mut().for_each(|x| *x += 1);
+        assert!(v.iter().cloned().eq(1..100_001));
+
+        let mut serial: SmallVec<[u32; 8]> = (0..10).collect();
+        let mut parallel = serial.clone();
+        serial.iter_mut().for_each(|x| *x *= 3);
+        (&mut parallel).into_par_iter().for_each(|x| *x *= 3);
+        assert_eq!(serial, parallel);
     }
 
     #[test]
-    fn test_truncate() {
-        let mut v: SmallVec<[Box<u8>; 8]> = SmallVec::new();
+    fn test_retain_bounded() {
+        for k in 0..6 {
+            let rc = Rc::new(());
+            let mut v: SmallVec<[(u32, Rc<()>); 2]> = (0..10).map(|i| (i, rc.clone())).collect();
+            let mut seen = Vec::new();
+            v.retain_bounded(|kept, &(x, _)| {
+                seen.push(kept);
+                kept < k && x % 3 != 0
+            });
+            let expected: Vec<u32> = (0..10).filter(|x| x % 3 != 0).take(k).collect();
+            assert!(v.iter().map(|&(x, _)| x).eq(expected.iter().cloned()));
+            assert_eq!(Rc::strong_count(&rc), 1 + v.len());
+            // Each element is visited once, and the count never exceeds the cap.
+            assert_eq!(seen.len(), 10);
+            assert!(seen.windows(2).all(|w| w[1] == w[0] || w[1] == w[0] + 1));
+            assert_eq!(*seen.last().unwrap(), ::std::cmp::min(k, 6));
+        }
+    }
 
-        for x in 0..8 {
-            v.push(Box::new(x));
+    #[cfg(feature = "spill_hook")]
+    #[test]
+    fn test_spill_hook() {
+        use std::cell::RefCell;
+        use set_spill_hook;
+
+        // The hook is global, so only record spills from this thread.
+        thread_local!(static SPILLS: RefCell<Vec<(usize, usize)>> = RefCell::new(Vec::new()));
+        fn record(inline_size: usize, new_cap: usize) {
+            SPILLS.with(|spills| spills.borrow_mut().push((inline_size, new_cap)));
         }
-        v.truncate(4);
+        let spills = || SPILLS.with(|spills| spills.borrow_mut().split_off(0));
 
-        assert_eq!(v.len(), 4);
-        assert!(!v.spilled());
+        set_spill_hook(Some(record));
+        let mut v: SmallVec<[u32; 4]> = SmallVec::new();
+        v.extend(0..4);
+        assert_eq!(spills(), &[]);
+        v.push(4);
+        assert_eq!(spills(), &[(4, 8)]);
+        // Growing on the heap and shrinking back are not spills...
+        v.extend(5..100);
+        v.truncate(2);
+        v.shrink_to_fit();
+        assert_eq!(spills(), &[]);
+        // ...but spilling again is.
+        v.reserve_exact(10);
+        assert_eq!(spills(), &[(4, 12)]);
 
-        assert_eq!(*v.swap_remove(1), 1);
-        assert_eq!(*v.remove(1), 3);
-        v.insert(1, Box::new(3));
+        let _ = SmallVec::<[u8; 2]>::with_capacity(3);
+        assert_eq!(spills(), &[(2, 3)]);
 
-        assert_eq!(&v.iter().map(|v| **v).collect::<Vec<_>>(), &[0, 3, 2]);
+        set_spill_hook(None);
+        let _ = SmallVec::<[u8; 2]>::with_capacity(3);
+        assert_eq!(spills(), &[]);
     }
 
     #[test]
-    fn test_insert_many() {
-        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
-        for x in 0..4 {
-            v.push(x);
+    fn test_from_vec_keep_capacity() {
+        let mut vec: Vec<String> = Vec::with_capacity(100);
+        vec.push("a".into());
+        vec.push("b".into());
+        let ptr = vec.as_ptr();
+        let mut v: SmallVec<[String; 4]> = SmallVec::from_vec_keep_capacity(vec);
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 100);
+        assert_eq!(&*v, &["a", "b"]);
+        if !cfg!(all(feature = "capacity_header", not(feature = "union"))) {
+            assert_eq!(v.as_ptr(), ptr);
         }
-        assert_eq!(v.len(), 4);
-        v.insert_many(1, [5, 6].iter().cloned());
-        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 5, 6, 1, 2, 3]);
-    }
+        let ptr = v.as_ptr();
+        v.extend((0..98).map(|i| i.to_string()));
+        assert_eq!(v.as_ptr(), ptr);
 
-    struct MockHintIter<T: Iterator>{x: T, hint: usize}
-    impl<T: Iterator> Iterator for MockHintIter<T> {
-        type Item = T::Item;
-        fn next(&mut self) -> Option<Self::Item> {self.x.next()}
-        fn size_hint(&self) -> (usize, Option<usize>) {(self.hint, None)}
+        let mut vec = Vec::with_capacity(3);
+        vec.push(1u8);
+        let v: SmallVec<[u8; 4]> = SmallVec::from_vec_keep_capacity(vec);
+        assert!(!v.spilled());
+        assert_eq!(&*v, &[1]);
     }
 
     #[test]
-    fn test_insert_many_short_hint() {
-        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
-        for x in 0..4 {
-            v.push(x);
+    fn test_eq_iter() {
+        for &len in &[3, 10] {
+            let v: SmallVec<[u32; 4]> = (0..len).collect();
+            assert_eq!(v.spilled(), len > 4);
+            assert!(v.eq_iter(0..len));
+            assert!(!v.eq_iter(0..len - 1));
+            assert!(!v.eq_iter(0..len + 1));
+            assert!(!v.eq_iter((0..len).rev()));
+            assert!(!v.eq_iter(None));
         }
-        assert_eq!(v.len(), 4);
-        v.insert_many(1, MockHintIter{x: [5, 6].iter().cloned(), hint: 5});
-        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 5, 6, 1, 2, 3]);
+        assert!(SmallVec::<[u32; 4]>::new().eq_iter(None));
+
+        // Stops at the first mismatch, and takes at most one item past the end.
+        let v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[0, 1, 2]);
+        let mut taken = 0;
+        assert!(!v.eq_iter([0, 5, 2, 3].iter().cloned().inspect(|_| taken += 1)));
+        assert_eq!(taken, 2);
+        let mut iter = 0..10;
+        assert!(!v.eq_iter(&mut iter));
+        assert_eq!(iter.next(), Some(4));
     }
 
     #[test]
-    fn test_insert_many_long_hint() {
-        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
-        for x in 0..4 {
-            v.push(x);
-        }
-        assert_eq!(v.len(), 4);
-        v.insert_many(1, MockHintIter{x: [5, 6].iter().cloned(), hint: 1});
-        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 5, 6, 1, 2, 3]);
+    fn test_shrink_to_fit_keep_spilled() {
+        let mut v: SmallVec<[u32; 4]> = SmallVec::with_capacity(64);
+        v.extend(0..2);
+        v.shrink_to_fit_keep_spilled();
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 5);
+        assert_eq!(&*v, &[0, 1]);
+
+        v.extend(2..20);
+        v.shrink_to_fit_keep_spilled();
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 20);
+        assert!(v.iter().cloned().eq(0..20));
+
+        v.clear();
+        v.shrink_to_fit_keep_spilled();
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 5);
+
+        // Inline vectors are not spilled by it.
+        let mut v: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1]);
+        v.shrink_to_fit_keep_spilled();
+        assert!(!v.spilled());
+        assert_eq!(v.capacity(), 4);
     }
 
-    #[cfg(feature = "std")]
     #[test]
-    // https://github.com/servo/rust-smallvec/issues/96
-    fn test_insert_many_panic() {
-        struct PanicOnDoubleDrop {
-            dropped: Box<bool>
+    fn test_extend_from_cow() {
+        use std::borrow::Cow;
+
+        let owned = || Cow::Owned(vec![4, 5, 6]);
+        let borrowed = || Cow::Borrowed(&[4, 5, 6][..]);
+        for data in &[owned(), borrowed()] {
+            // Staying inline, crossing the spill boundary, and appending while spilled.
+            let mut v: SmallVec<[u8; 4]> = SmallVec::from_slice(&[1]);
+            v.extend_from_cow(data.clone());
+            assert!(!v.spilled());
+            v.extend_from_cow(data.clone());
+            assert!(v.spilled());
+            v.extend_from_cow(data.clone());
+            assert_eq!(&*v, &[1, 4, 5, 6, 4, 5, 6, 4, 5, 6]);
         }
 
-        impl Drop for PanicOnDoubleDrop {
-            fn drop(&mut self) {
-                assert!(!*self.dropped, "already dropped");
-                *self.dropped = true;
-            }
+        // An empty inline vector takes over a large owned buffer.
+        let vec = (0..10).collect::<Vec<u8>>();
+        let ptr = vec.as_ptr();
+        let mut v: SmallVec<[u8; 4]> = SmallVec::new();
+        v.extend_from_cow(Cow::Owned(vec));
+        assert!(v.iter().cloned().eq(0..10));
+        if !cfg!(all(feature = "capacity_header", not(feature = "union"))) {
+            assert_eq!(v.as_ptr(), ptr);
         }
+    }
 
-        struct BadIter;
-        impl Iterator for BadIter {
-            type Item = PanicOnDoubleDrop;
-            fn size_hint(&self) -> (usize, Option<usize>) { (1, None) }
-            fn next(&mut self) -> Option<Self::Item> { panic!() }
+    #[test]
+    fn test_truncate_drain() {
+        let rc = Rc::new(());
+        let mut v: SmallVec<[(u32, Rc<()>); 2]> = (0..6).map(|i| (i, rc.clone())).collect();
+
+        let tail: Vec<u32> = v.truncate_drain(3).map(|(i, _)| i).collect();
+        assert_eq!(tail, &[3, 4, 5]);
+        assert_eq!(v.len(), 3);
+        assert_eq!(Rc::strong_count(&rc), 4);
+
+        // Dropping the iterator early still drops the rest of the tail.
+        {
+            let mut tail = v.truncate_drain(1);
+            assert_eq!(tail.next().map(|(i, _)| i), Some(1));
         }
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].0, 0);
+        assert_eq!(Rc::strong_count(&rc), 2);
 
-        let mut vec: SmallVec<[PanicOnDoubleDrop; 0]> = vec![
-            PanicOnDoubleDrop { dropped: Box::new(false) },
-            PanicOnDoubleDrop { dropped: Box::new(false) },
-        ].into();
-        let result = ::std::panic::catch_unwind(move || {
-            vec.insert_many(0, BadIter);
-        });
-        assert!(result.is_err());
+        assert_eq!(v.truncate_drain(1).count(), 0);
+        assert_eq!(v.truncate_drain(10).count(), 0);
+        assert_eq!(v.len(), 1);
     }
 
+    #[cfg(feature = "bytemuck")]
     #[test]
-    #[should_panic]
-    fn test_invalid_grow() {
-        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
-        v.extend(0..8);
-        v.grow(5);
+    fn test_bytes_round_trip() {
+        use bytemuck::PodCastError;
+
+        for &len in &[0, 3, 10] {
+            let v: SmallVec<[u32; 4]> = (0..len).map(|x| x * 0x01010101).collect();
+            let bytes = v.as_bytes();
+            assert_eq!(bytes.len(), 4 * len as usize);
+            let back = SmallVec::<[u32; 4]>::from_bytes(bytes).unwrap();
+            assert_eq!(back, v);
+            assert_eq!(back.spilled(), len > 4);
+
+            // The input does not have to be aligned.
+            let mut shifted = vec![0u8];
+            shifted.extend_from_slice(bytes);
+            assert_eq!(SmallVec::<[u32; 4]>::from_bytes(&shifted[1..]).unwrap(), v);
+        }
+
+        assert_eq!(SmallVec::<[u32; 4]>::from_bytes(&[1, 2, 3, 4, 5]),
+                   Err(PodCastError::OutputSliceWouldHaveSlop));
+        assert_eq!(SmallVec::<[(); 4]>::from_bytes(&[]), Err(PodCastError::SizeMismatch));
     }
 
     #[test]
-    fn test_insert_from_slice() {
-        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
-        for x in 0..4 {
-            v.push(x);
+    fn test_into_iter_peek() {
+        for &len in &[3u32, 10] {
+            let v: SmallVec<[String; 4]> = (0..len).map(|i| i.to_string()).collect();
+            let mut iter = v.into_iter();
+            assert_eq!(iter.next().unwrap(), "0");
+            assert_eq!(iter.next_back().unwrap(), (len - 1).to_string());
+
+            // Peeking does not advance either end.
+            for _ in 0..2 {
+                assert_eq!(iter.peek().unwrap(), "1");
+                assert_eq!(iter.peek_back().unwrap(), &(len - 2).to_string());
+                assert_eq!(iter.len(), len as usize - 2);
+            }
+            assert_eq!(iter.next().unwrap(), "1");
+            assert_eq!(iter.peek().map(|s| &**s), if len == 3 { None } else { Some("2") });
         }
-        assert_eq!(v.len(), 4);
-        v.insert_from_slice(1, &[5, 6]);
-        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 5, 6, 1, 2, 3]);
+
+        let mut iter = SmallVec::<[u8; 2]>::from_slice(&[7]).into_iter();
+        assert_eq!((iter.peek(), iter.peek_back()), (Some(&7), Some(&7)));
+        iter.next();
+        assert_eq!((iter.peek(), iter.peek_back()), (None, None));
     }
 
     #[test]
-    fn test_extend_from_slice() {
-        let mut v: SmallVec<[u8; 8]> = SmallVec::new();
-        for x in 0..4 {
-            v.push(x);
-        }
-        assert_eq!(v.len(), 4);
-        v.extend_from_slice(&[5, 6]);
-        assert_eq!(&v.iter().map(|v| *v).collect::<Vec<_>>(), &[0, 1, 2, 3, 5, 6]);
+    fn test_clone_keep_capacity() {
+        let mut v: SmallVec<[String; 2]> = SmallVec::with_capacity(10);
+        v.push("a".to_owned());
+        v.push("b".to_owned());
+        v.push("c".to_owned());
+        v.truncate(1);
+
+        assert_eq!(v.clone().capacity(), 2);
+        let c = v.clone_keep_capacity();
+        assert_eq!(c.capacity(), v.capacity());
+        assert!(c.spilled());
+        assert_eq!(&*c, &["a"]);
+
+        let inline: SmallVec<[String; 2]> = SmallVec::new();
+        let c = inline.clone_keep_capacity();
+        assert_eq!(c.capacity(), inline.capacity());
+        assert!(!c.spilled());
     }
 
     #[test]
@@ -1962,6 +6264,29 @@ mod tests {
         v.push(DropPanic);
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_drop_panic_frees_heap_buffer() {
+        struct DropPanic(bool);
+
+        impl Drop for DropPanic {
+            fn drop(&mut self) {
+                if self.0 {
+                    panic!("drop");
+                }
+            }
+        }
+
+        let v: SmallVec<[DropPanic; 1]> =
+            vec![DropPanic(false), DropPanic(true), DropPanic(false)].into();
+        assert!(v.spilled());
+        let ptr = v.as_ptr();
+        assert!(frees(ptr, move || {
+            let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(move || drop(v)));
+            assert!(result.is_err());
+        }));
+    }
+
     #[test]
     fn test_eq() {
         let mut a: SmallVec<[u32; 2]> = SmallVec::new();
@@ -2023,6 +6348,120 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_hash_map_byte_keys() {
+        use std::collections::HashMap;
+
+        let inline: SmallVec<[u8; 8]> = SmallVec::from_slice(b"key");
+        let spilled: SmallVec<[u8; 2]> = SmallVec::from_slice(b"key");
+        assert_eq!(hash_of(&inline), hash_of(&b"key"[..]));
+        assert_eq!(hash_of(&spilled), hash_of(&b"key".to_vec()));
+
+        let mut map = HashMap::new();
+        map.insert(inline, 1u32);
+        assert_eq!(map.get(&b"key"[..]), Some(&1));
+        assert_eq!(map.get(&b"kez"[..]), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_eq_hash_across_spill_states() {
+        for len in 0..10 {
+            let contents: Vec<u32> = (0..len).collect();
+            let spilled: SmallVec<[u32; 4]> = {
+                let mut v = SmallVec::with_capacity(16);
+                v.extend(contents.iter().cloned());
+                v
+            };
+            let unforced: SmallVec<[u32; 4]> = SmallVec::from_slice(&contents);
+            assert!(spilled.spilled());
+            assert_eq!(unforced.spilled(), len > 4);
+
+            assert_eq!(spilled, unforced);
+            assert_eq!(&spilled[..], &contents[..]);
+            assert_eq!(&unforced[..], &contents[..]);
+            assert_eq!(hash_of(&spilled), hash_of(&unforced));
+            assert_eq!(hash_of(&spilled), hash_of(&contents));
+            assert_eq!(hash_of(&unforced), hash_of(&contents[..]));
+
+            // A different inline size does not matter either.
+            let other: SmallVec<[u32; 1]> = SmallVec::from_slice(&contents);
+            assert_eq!(unforced, other);
+            assert_eq!(hash_of(&unforced), hash_of(&other));
+
+            // The same holds for byte vectors, with one or both of them spilled.
+            let bytes: Vec<u8> = contents.iter().map(|&x| x as u8).collect();
+            let four: SmallVec<[u8; 4]> = SmallVec::from_slice(&bytes);
+            let eight: SmallVec<[u8; 8]> = SmallVec::from_slice(&bytes);
+            assert_eq!(four, eight);
+            assert_eq!(hash_of(&four), hash_of(&eight));
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut v: SmallVec<[u8; 2]> = SmallVec::from_slice(&[1, 2]);
+        assert_eq!(format!("{:?}", v), "[1, 2]");
+        assert_eq!(format!("{:#?}", v),
+                   "SmallVec {\n    elements: [\n        1,\n        2,\n    ],\n    \
+                    spilled: false,\n    capacity: 2,\n}");
+        v.push(3);
+        assert_eq!(format!("{:?}", v), "[1, 2, 3]");
+        assert!(format!("{:#?}", v).ends_with("spilled: true,\n    capacity: 4,\n}"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_ord_btree_map_across_spill_states() {
+        use std::cmp::Ordering;
+        use std::collections::BTreeMap;
+
+        type Key = SmallVec<[u8; 2]>;
+        let empty = Key::new();
+        assert!(empty < Key::from_slice(&[0]));
+        assert!(empty < Key::from_slice(&[0, 0, 0]));
+
+        let short = Key::from_slice(&[1, 2]);
+        let long = Key::from_slice(&[1, 2, 3]);
+        assert!(!short.spilled() && long.spilled());
+        assert!(short < long);
+        assert_eq!(short.cmp(&long), vec![1u8, 2].cmp(&vec![1, 2, 3]));
+
+        let mut forced = Key::with_capacity(8);
+        forced.extend_from_slice(&[1, 2]);
+        assert!(forced.spilled());
+        assert_eq!(forced.cmp(&short), Ordering::Equal);
+
+        let keys: &[&[u8]] = &[&[3], &[1, 2, 3], &[], &[1, 2], &[1, 3], &[0, 9, 9, 9], &[1]];
+        let mut map = BTreeMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            map.insert(Key::from_slice(key), i as u32);
+        }
+        map.insert(forced, 100);
+        let mut sorted: Vec<Vec<u8>> = keys.iter().map(|k| k.to_vec()).collect();
+        sorted.sort();
+        assert!(map.keys().map(|k| k.to_vec()).eq(sorted));
+        assert_eq!(map.len(), keys.len());
+        assert_eq!(map.get(&[1, 2][..]), Some(&100));
+    }
+
+    #[test]
+    fn test_send_sync() {
+        use {Drain, IntoIter};
+
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+
+        assert_send::<SmallVec<[u32; 4]>>();
+        assert_sync::<SmallVec<[u32; 4]>>();
+        assert_send::<&SmallVec<[u32; 4]>>();
+        assert_send::<IntoIter<[u32; 4]>>();
+        assert_sync::<IntoIter<[u32; 4]>>();
+        assert_send::<Drain<u32>>();
+        assert_sync::<Drain<u32>>();
+    }
+
     #[test]
     fn test_as_ref() {
         let mut a: SmallVec<[u32; 2]> = SmallVec::new();
@@ -2043,7 +6482,7 @@ mod tests {
         assert_eq!(a.as_mut(), [1, 2]);
         a.push(3);
         assert_eq!(a.as_mut(), [1, 2, 3]);
-        a.as_mut()[1] = 4;
+        AsMut::<[u32]>::as_mut(&mut a)[1] = 4;
         assert_eq!(a.as_mut(), [1, 4, 3]);
     }
 
@@ -2112,11 +6551,43 @@ mod tests {
         assert_eq!(&SmallVec::<[u32; 2]>::from_slice(&[1, 2, 3][..])[..], [1, 2, 3]);
     }
 
+    #[test]
+    fn test_clone() {
+        let v: SmallVec<[u8; 16]> = SmallVec::from_slice(&[1, 2, 3]);
+        let c = v.clone();
+        assert_eq!(c, v);
+        assert!(!c.spilled());
+
+        let v: SmallVec<[u8; 2]> = (0..100).collect();
+        let c = v.clone();
+        assert_eq!(c, v);
+        assert!(c.spilled());
+
+        let v: SmallVec<[Box<u8>; 2]> = (0..3).map(Box::new).collect();
+        let c = v.clone();
+        assert_eq!(c, v);
+        assert_eq!(c.capacity(), 3);
+    }
+
+    #[test]
+    fn test_from_iter_exact_size() {
+        let v = SmallVec::<[u8; 4]>::from_iter(0u8..200);
+        assert_eq!(v.len(), 200);
+        assert_eq!(v.capacity(), 200);
+
+        let v = SmallVec::<[u8; 4]>::from_iter(0u8..3);
+        assert!(!v.spilled());
+
+        // Only the lower bound is known, so capacity is rounded up.
+        let v = SmallVec::<[u8; 4]>::from_iter((0u8..200).filter(|_| true));
+        assert_eq!(v.capacity(), 256);
+    }
+
     #[test]
     fn test_exact_size_iterator() {
         let mut vec = SmallVec::<[u32; 2]>::from(&[1, 2, 3][..]);
         assert_eq!(vec.clone().into_iter().len(), 3);
-        assert_eq!(vec.drain().len(), 3);
+        assert_eq!(vec.drain(..).len(), 3);
     }
 
     #[test]
@@ -2154,6 +6625,34 @@ mod tests {
         assert_eq!(vec.into_vec(), vec![0, 1, 2]);
     }
 
+    #[test]
+    fn test_vec_conversions() {
+        fn to_vec<V: Into<Vec<u32>>>(v: V) -> Vec<u32> {
+            v.into()
+        }
+
+        let spilled: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2, 3]);
+        let ptr = spilled.as_ptr();
+        let vec = to_vec(spilled);
+        assert_eq!(vec, &[1, 2, 3]);
+        if !cfg!(all(feature = "capacity_header", not(feature = "union"))) {
+            assert_eq!(vec.as_ptr(), ptr);
+        }
+        let ptr = vec.as_ptr();
+        let back: SmallVec<[u32; 2]> = vec.into();
+        assert_eq!(&*back, &[1, 2, 3]);
+        if !cfg!(all(feature = "capacity_header", not(feature = "union"))) {
+            assert_eq!(back.as_ptr(), ptr);
+        }
+
+        let inline: SmallVec<[u32; 4]> = SmallVec::from_slice(&[1, 2]);
+        let vec = to_vec(inline);
+        assert_eq!(vec, &[1, 2]);
+        let back: SmallVec<[u32; 4]> = vec.into();
+        assert!(!back.spilled());
+        assert_eq!(&*back, &[1, 2]);
+    }
+
     #[test]
     fn test_into_inner() {
         let vec = SmallVec::<[u8; 2]>::from_iter(0..2);
@@ -2199,6 +6698,119 @@ mod tests {
         drop(small_vec);
     }
 
+    #[test]
+    fn test_pop_n() {
+        let rc = Rc::new(());
+        let mut v: SmallVec<[Rc<()>; 2]> = (0..6).map(|_| rc.clone()).collect();
+        assert!(v.spilled());
+
+        let popped = v.pop_n(2);
+        assert_eq!((v.len(), popped.len()), (4, 2));
+        assert!(!popped.spilled());
+        let popped = v.pop_n(3);
+        assert_eq!((v.len(), popped.len()), (1, 3));
+        assert!(popped.spilled());
+        assert_eq!(Rc::strong_count(&rc), 7);
+        drop(popped);
+        assert_eq!(Rc::strong_count(&rc), 4);
+
+        assert_eq!(v.pop_n(5).len(), 1);
+        assert!(v.is_empty());
+        assert!(v.pop_n(1).is_empty());
+
+        let mut v: SmallVec<[u32; 2]> = SmallVec::from_slice(&[1, 2, 3, 4, 5]);
+        assert_eq!(&*v.pop_n(5), &[1, 2, 3, 4, 5]);
+        assert!(v.is_empty());
+        v.extend(6..9);
+        assert_eq!(&*v.pop_n(0), &[] as &[u32]);
+        assert_eq!(&*v.pop_n(2), &[7, 8]);
+        assert_eq!(&*v, &[6]);
+    }
+
+    #[test]
+    fn test_retain_with_index() {
+        let mut sv: SmallVec<[u32; 2]> = SmallVec::from_slice(&[5, 5, 6, 7, 7, 8, 9]);
+        let mut seen = Vec::new();
+        sv.retain_with_index(|i, x| {
+            seen.push(i);
+            *x += 1;
+            i % 3 != 0 && *x != 8
+        });
+        assert_eq!(seen, (0..7).collect::<Vec<_>>());
+        assert_eq!(&*sv, &[6, 7, 9]);
+    }
+
+    #[test]
+    fn test_map_in_place() {
+        let mut v: SmallVec<[Box<u32>; 2]> = (0..5).map(Box::new).collect();
+        v.map_in_place(|x| Box::new(*x * 10));
+        assert_eq!(v.iter().map(|x| **x).collect::<Vec<_>>(), &[0, 10, 20, 30, 40]);
+
+        let rcs: Vec<Rc<()>> = (0..5).map(|_| Rc::new(())).collect();
+        let mut v: SmallVec<[Rc<()>; 2]> = rcs.iter().cloned().collect();
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            let mut i = 0;
+            v.map_in_place(|x| {
+                i += 1;
+                if i == 3 { panic!() }
+                x
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(v.len(), 2);
+        let counts: Vec<usize> = rcs.iter().map(Rc::strong_count).collect();
+        assert_eq!(counts, &[2, 2, 1, 1, 1]);
+        drop(v);
+        assert!(rcs.iter().all(|rc| Rc::strong_count(rc) == 1));
+    }
+
+    #[test]
+    fn test_retain_drain() {
+        let rc = Rc::new(());
+        let mut v: SmallVec<[Rc<()>; 2]> = (0..8).map(|_| rc.clone()).collect();
+        let mut i = 0;
+        assert_eq!(v.retain_drain(|_| { i += 1; i % 2 == 0 }), 4);
+        assert_eq!(Rc::strong_count(&rc), 5);
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 8);
+
+        assert_eq!(v.retain_drain(|_| true), 0);
+        assert_eq!(v.retain_drain(|_| { i += 1; i % 2 == 0 }), 2);
+        // The remaining elements fit inline, but the allocation is kept.
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 8);
+        assert_eq!(v.len(), 2);
+        assert_eq!(Rc::strong_count(&rc), 3);
+
+        assert_eq!(v.retain_drain(|_| false), 2);
+        assert!(v.is_empty());
+        assert!(v.spilled());
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_retain_drain_and_shrink() {
+        let rc = Rc::new(());
+        let mut v: SmallVec<[Rc<()>; 2]> = (0..8).map(|_| rc.clone()).collect();
+        let mut i = 0;
+        assert_eq!(v.retain_drain_and_shrink(|_| { i += 1; i % 2 == 0 }), 4);
+        assert_eq!(Rc::strong_count(&rc), 5);
+        // Four elements do not fit inline, so the allocation is kept.
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 8);
+
+        assert_eq!(v.retain_drain_and_shrink(|_| true), 0);
+        assert!(v.spilled());
+        assert_eq!(v.retain_drain_and_shrink(|_| { i += 1; i % 2 == 0 }), 2);
+        assert!(!v.spilled());
+        assert_eq!(v.len(), 2);
+        assert_eq!(Rc::strong_count(&rc), 3);
+
+        assert_eq!(v.retain_drain_and_shrink(|_| false), 2);
+        assert!(v.is_empty());
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
     #[test]
     fn test_retain() {
         // Test inline data storate
@@ -2253,6 +6865,48 @@ mod tests {
         assert_eq!(no_dupes.len(), 5);
     }
 
+    #[test]
+    fn test_sort_unique() {
+        let mut v: SmallVec<[i32; 4]> = (0..100).map(|i| i % 3).collect();
+        assert!(v.spilled());
+        v.sort_unique();
+        assert_eq!(&*v, &[0, 1, 2]);
+        assert!(!v.spilled());
+
+        let mut v: SmallVec<[i32; 4]> = (0..100).rev().map(|i| i % 10).collect();
+        v.sort_unique();
+        assert_eq!(&*v, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 100);
+
+        let mut empty: SmallVec<[i32; 4]> = SmallVec::new();
+        empty.sort_unique();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_binary_search() {
+        let mut v: SmallVec<[u32; 4]> = SmallVec::new();
+        for &x in &[5, 1, 4, 1, 3, 9, 2, 6] {
+            match v.binary_search(&x) {
+                Ok(_) => {}
+                Err(i) => v.insert(i, x),
+            }
+        }
+        assert_eq!(&*v, &[1, 2, 3, 4, 5, 6, 9]);
+        assert!(v.spilled());
+        assert_eq!(v.binary_search(&6), Ok(5));
+        assert_eq!(v.binary_search(&7), Err(6));
+        assert_eq!(v.binary_search_by(|x| x.cmp(&0)), Err(0));
+        assert_eq!(v.binary_search_by_key(&18, |x| x * 2), Ok(6));
+
+        let v: SmallVec<[(u32, char); 4]> = SmallVec::from_slice(&[(1, 'a'), (3, 'b'), (5, 'c')]);
+        assert!(!v.spilled());
+        assert_eq!(v.binary_search(&(3, 'b')), Ok(1));
+        assert_eq!(v.binary_search_by_key(&'c', |&(_, c)| c), Ok(2));
+        assert_eq!(v.binary_search_by(|&(n, _)| n.cmp(&4)), Err(2));
+    }
+
     #[test]
     fn test_resize() {
         let mut v: SmallVec<[i32; 8]> = SmallVec::new();
@@ -2264,6 +6918,64 @@ mod tests {
         assert_eq!(v[..], [1, 0][..]);
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_write_allocations() {
+        use io::Write;
+
+        let mut v: SmallVec<[u8; 16]> = SmallVec::new();
+        let ((), allocs) = count_allocs(|| {
+            for i in 0..10_000 {
+                let n = if i % 2 == 0 { 1 } else { 100 };
+                v.write_all(&[i as u8; 100][..n]).unwrap();
+            }
+        });
+        assert_eq!(v.len(), 5_000 * 101);
+        // One allocation per doubling from the inline size up to the final capacity.
+        assert_eq!(v.capacity(), v.len().next_power_of_two());
+        assert!(allocs <= 16, "{} allocations", allocs);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_cursor() {
+        use io::{Read, Seek, SeekFrom, Write};
+        use SmallVecCursor;
+
+        let mut c = SmallVecCursor::new(SmallVec::<[u8; 8]>::new());
+        c.write_all(&[0, 0, 1, 2, 3]).unwrap();
+        assert_eq!(c.position(), 5);
+
+        // Backpatch a length prefix.
+        assert_eq!(c.seek(SeekFrom::Current(-5)).unwrap(), 0);
+        c.write_all(&[0, 3]).unwrap();
+        assert_eq!(&**c.get_ref(), &[0, 3, 1, 2, 3]);
+
+        // Overwrite across the end.
+        c.seek(SeekFrom::End(-1)).unwrap();
+        c.write_all(&[4, 5]).unwrap();
+        assert_eq!(&**c.get_ref(), &[0, 3, 1, 2, 4, 5]);
+
+        // Writing past the end zero-fills the gap.
+        assert_eq!(c.seek(SeekFrom::End(2)).unwrap(), 8);
+        assert_eq!(c.get_ref().len(), 6);
+        c.write_all(&[9]).unwrap();
+        assert_eq!(&**c.get_ref(), &[0, 3, 1, 2, 4, 5, 0, 0, 9]);
+        assert!(c.get_ref().spilled());
+
+        assert!(c.seek(SeekFrom::Current(-10)).is_err());
+        assert!(c.seek(SeekFrom::Current(i64::min_value())).is_err());
+        assert_eq!(c.position(), 9);
+
+        c.set_position(6);
+        let mut buf = [1; 4];
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [0, 0, 9, 1]);
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+        c.set_position(100);
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+    }
+
     #[cfg(feature = "std")]
     #[test]
     fn test_write() {
@@ -2274,11 +6986,11 @@ mod tests {
         let mut small_vec: SmallVec<[u8; 2]> = SmallVec::new();
         let len = small_vec.write(&data[..]).unwrap();
         assert_eq!(len, 5);
-        assert_eq!(small_vec.as_ref(), data.as_ref());
+        assert_eq!(AsRef::<[u8]>::as_ref(&small_vec), data.as_ref());
 
         let mut small_vec: SmallVec<[u8; 2]> = SmallVec::new();
         small_vec.write_all(&data[..]).unwrap();
-        assert_eq!(small_vec.as_ref(), data.as_ref());
+        assert_eq!(AsRef::<[u8]>::as_ref(&small_vec), data.as_ref());
     }
 
     #[cfg(feature = "serde")]
@@ -2302,4 +7014,20 @@ mod tests {
         let decoded: SmallVec<[i32; 2]> = deserialize(&encoded).unwrap();
         assert_eq!(small_vec, decoded);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_strings_and_truncated_input() {
+        use self::bincode::{deserialize, serialize};
+        let small_vec: SmallVec<[String; 2]> =
+            ["a", "bc", "def", "ghij"].iter().map(|s| s.to_string()).collect();
+        let encoded = serialize(&small_vec).unwrap();
+        let decoded: SmallVec<[String; 2]> = deserialize(&encoded).unwrap();
+        assert_eq!(small_vec, decoded);
+
+        // An error part-way through the sequence is reported, and the elements read so far
+        // are dropped rather than returned.
+        let truncated = &encoded[..encoded.len() - 2];
+        assert!(deserialize::<SmallVec<[String; 2]>>(truncated).is_err());
+    }
 }